@@ -1,18 +1,330 @@
 use rand::prelude::SliceRandom;
-use rand::thread_rng;
+use rand::{thread_rng, Rng};
+use std::error::Error;
+use std::fmt;
 use std::fs::File;
-use std::io::Lines;
 use std::io::{BufRead, BufReader};
+use std::sync::Arc;
+
+use crate::base::functions::{mean, variance};
 
 #[derive(Debug, Clone)] // copy is to allow non-consumption when initializing multiple gp's...
 pub struct Data {
-    // NOTE! Outputs to be predicted is assumed to be the last column!
+    // NOTE! Outputs to be predicted are moved to the last column of train and test!
     dimensions: usize,
+    ///File the rows were read from, or "series" for data held in memory, used in errors
+    name: String,
+    ///All rows as read from the file, kept so the data can be split again.
+    /// Shared between the copies made for folds and targets
+    rows: Arc<Vec<Vec<f32>>>,
+    ///Names of every column of the rows, in file order
+    columns: Vec<String>,
+    ///Column of the rows holding the outputs to be predicted
+    target: usize,
+    ///Every column declared as a target, none of them is used as an input
+    targets: Vec<usize>,
+    ///Column holding the weight of each row, not used as an input
+    weight: Option<usize>,
+    ///Names of the input variables in the layout of train and test
+    names: Vec<String>,
+    ///Fraction of the rows used for testing
+    test_split: f32,
+    ///Fraction of the rows used for validation, taken out of the training rows
+    validation_split: f32,
+    ///Keep the rows in file order when splitting: train first, then validation, then test
+    chronological: bool,
+    ///Indices of the rows in each partition
+    train_rows: Vec<usize>,
+    validation_rows: Vec<usize>,
+    test_rows: Vec<usize>,
+    ///Scaling of the input columns and of the target column
+    feature_scaling: Scaling,
+    target_scaling: Scaling,
+    ///Scaling parameters fitted on the training data, in the layout of train and test
+    scales: Vec<ColumnScale>,
     train: Vec<Vec<f32>>,
+    ///Empty unless a validation split is set
+    validation: Vec<Vec<f32>>,
     test: Vec<Vec<f32>>,
+    ///Weights of the rows in each partition, empty unless a weight column is set
+    train_weights: Vec<f32>,
+    validation_weights: Vec<f32>,
+    test_weights: Vec<f32>,
     // to [j][i], e.g. test[0] gets the first variable for all instances ;)
 }
 
+///Errors found while loading and splitting a dataset.
+/// Lines and columns are counted from 1 as shown by a text editor
+#[derive(Debug)]
+pub enum DataError {
+    ///The file could not be opened or read
+    Io { file: String, error: std::io::Error },
+    ///A token could not be parsed as a float
+    Parse {
+        file: String,
+        line: usize,
+        column: usize,
+        token: String,
+    },
+    ///A row has a different number of columns than the first row
+    Ragged {
+        file: String,
+        line: usize,
+        expected: usize,
+        found: usize,
+    },
+    ///The file has no rows of data
+    Empty { file: String },
+    ///The number of names does not match the number of series
+    Names {
+        file: String,
+        names: usize,
+        series: usize,
+    },
+    ///A series has a different length than the first series
+    SeriesLength {
+        file: String,
+        series: usize,
+        expected: usize,
+        found: usize,
+    },
+    ///The train or test partition would have no rows
+    EmptySplit {
+        file: String,
+        partition: &'static str,
+        rows: usize,
+        test_split: f32,
+    },
+    ///The requested target column does not exist
+    MissingTarget { file: String, target: String },
+    ///The test split is not strictly between 0 and 1
+    SplitRange { file: String, test_split: f32 },
+    ///There is no column left to use as an input
+    NoInputs { file: String },
+    ///The lags or horizon are zero or need more rows than there are
+    Lags {
+        file: String,
+        rows: usize,
+        lags: usize,
+        horizon: usize,
+    },
+}
+
+impl fmt::Display for DataError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DataError::Io { file, error } => write!(f, "{}: cannot open file: {}", file, error),
+            DataError::Parse {
+                file,
+                line,
+                column,
+                token,
+            } => write!(
+                f,
+                "{}:{}:{}: cannot parse '{}' as a number",
+                file, line, column, token
+            ),
+            DataError::Ragged {
+                file,
+                line,
+                expected,
+                found,
+            } => write!(
+                f,
+                "{}:{}: expected {} columns but found {}",
+                file, line, expected, found
+            ),
+            DataError::Empty { file } => write!(f, "{}: no rows of data", file),
+            DataError::Names {
+                file,
+                names,
+                series,
+            } => write!(f, "{}: {} names given for {} series", file, names, series),
+            DataError::SeriesLength {
+                file,
+                series,
+                expected,
+                found,
+            } => write!(
+                f,
+                "{}: series {} has {} values but the first has {}",
+                file, series, found, expected
+            ),
+            DataError::EmptySplit {
+                file,
+                partition,
+                rows,
+                test_split,
+            } => write!(
+                f,
+                "{}: a test split of {} leaves the {} data empty for {} rows",
+                file, test_split, partition, rows
+            ),
+            DataError::MissingTarget { file, target } => {
+                write!(f, "{}: target column {} not found", file, target)
+            }
+            DataError::SplitRange { file, test_split } => write!(
+                f,
+                "{}: test split of {} is not between 0 and 1",
+                file, test_split
+            ),
+            DataError::NoInputs { file } => write!(f, "{}: no input columns", file),
+            DataError::Lags {
+                file,
+                rows,
+                lags,
+                horizon,
+            } => write!(
+                f,
+                "{}: cannot build {} lags with a horizon of {} from {} rows",
+                file, lags, horizon, rows
+            ),
+        }
+    }
+}
+
+impl Error for DataError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            DataError::Io { error, .. } => Some(error),
+            _ => None,
+        }
+    }
+}
+
+///How a column is rescaled before evolution
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scaling {
+    ///Keep the original values
+    None,
+    ///Subtract the mean and divide by the standard deviation
+    Standard,
+    ///Map the minimum to 0 and the maximum to 1
+    MinMax,
+}
+
+///Fitted parameters of a column scaling, scaled = (original - offset) / scale
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ColumnScale {
+    pub offset: f32,
+    pub scale: f32,
+}
+
+impl ColumnScale {
+    ///The scaling that leaves values unchanged
+    pub fn identity() -> ColumnScale {
+        ColumnScale {
+            offset: 0.0,
+            scale: 1.0,
+        }
+    }
+
+    ///Fit the scaling parameters on a column of values.
+    /// A constant column is only shifted, never divided by zero
+    pub fn fit(values: &[f32], scaling: Scaling) -> ColumnScale {
+        let (offset, scale) = match scaling {
+            Scaling::None => return ColumnScale::identity(),
+            Scaling::Standard => (mean(values), variance(values).sqrt()),
+            Scaling::MinMax => {
+                let min = values.iter().cloned().fold(f32::INFINITY, f32::min);
+                let max = values.iter().cloned().fold(f32::NEG_INFINITY, f32::max);
+                (min, max - min)
+            }
+        };
+        ColumnScale {
+            offset,
+            scale: if scale > 0.0 { scale } else { 1.0 },
+        }
+    }
+
+    pub fn is_identity(&self) -> bool {
+        *self == ColumnScale::identity()
+    }
+
+    ///Original units to scaled space
+    pub fn apply(&self, x: f32) -> f32 {
+        (x - self.offset) / self.scale
+    }
+
+    ///Scaled space back to original units
+    pub fn invert(&self, x: f32) -> f32 {
+        x * self.scale + self.offset
+    }
+}
+
+///Selects a column of a dataset, such as the outputs to be predicted or the sample weights
+#[derive(Debug, Clone)]
+pub enum Column {
+    ///The last column of the file
+    Last,
+    ///Column at a zero based index
+    Index(usize),
+    ///Column with the given header name
+    Name(String),
+}
+
+impl Column {
+    ///Index of the selected column among the named columns
+    fn resolve(&self, columns: &[String]) -> Option<usize> {
+        match self {
+            Column::Last => columns.len().checked_sub(1),
+            Column::Index(j) => Some(*j).filter(|&j| j < columns.len()),
+            Column::Name(name) => columns.iter().position(|c| c == name),
+        }
+    }
+}
+
+///Options for loading a delimited text file such as CSV or TSV
+#[derive(Debug, Clone)]
+pub struct CsvOptions {
+    delimiter: char,
+    header: bool,
+    target: Column,
+    test_split: f32,
+}
+
+impl Default for CsvOptions {
+    fn default() -> Self {
+        CsvOptions::new()
+    }
+}
+
+impl CsvOptions {
+    ///Comma delimited with a header row, the last column as target and half of the rows for testing
+    pub fn new() -> CsvOptions {
+        CsvOptions {
+            delimiter: ',',
+            header: true,
+            target: Column::Last,
+            test_split: 0.5,
+        }
+    }
+
+    ///Set the field delimiter, e.g. '\t' for TSV files
+    pub fn set_delimiter(mut self, delimiter: char) -> CsvOptions {
+        self.delimiter = delimiter;
+        self
+    }
+
+    ///Set whether the first row holds the column names
+    pub fn set_header(mut self, header: bool) -> CsvOptions {
+        self.header = header;
+        self
+    }
+
+    ///Set the column holding the outputs to be predicted
+    pub fn set_target(mut self, target: Column) -> CsvOptions {
+        self.target = target;
+        self
+    }
+
+    ///Set the fraction of the rows used for testing
+    pub fn set_test_split(mut self, test_split: f32) -> CsvOptions {
+        self.test_split = test_split;
+        self
+    }
+}
+
 ///Getter and Utility methods for the data
 impl Data {
     pub fn train(&self) -> &Vec<Vec<f32>> {
@@ -21,76 +333,657 @@ impl Data {
     pub fn test(&self) -> &Vec<Vec<f32>> {
         &self.test
     }
+    pub fn validation(&self) -> &Vec<Vec<f32>> {
+        &self.validation
+    }
+    ///True if the data has a validation partition
+    pub fn has_validation(&self) -> bool {
+        !self.validation.is_empty()
+    }
     pub fn dimensions(&self) -> usize {
         self.dimensions
     }
+    ///Fails if no column is left to use as an input, e.g. a single series before set_lags()
+    pub fn check_inputs(&self) -> Result<(), DataError> {
+        match self.dimensions {
+            0 => Err(DataError::NoInputs {
+                file: self.name.clone(),
+            }),
+            _ => Ok(()),
+        }
+    }
     pub fn train_targets(&self) -> &Vec<f32> {
         &self.train[self.train.len() - 1]
     }
     pub fn test_targets(&self) -> &Vec<f32> {
         &self.test[self.test.len() - 1]
     }
+    pub fn validation_targets(&self) -> &Vec<f32> {
+        &self.validation[self.validation.len() - 1]
+    }
+
+    ///Weights of the training rows, None unless a weight column is set
+    pub fn train_weights(&self) -> Option<&[f32]> {
+        self.weight.map(|_| self.train_weights.as_slice())
+    }
+    pub fn validation_weights(&self) -> Option<&[f32]> {
+        self.weight.map(|_| self.validation_weights.as_slice())
+    }
+    pub fn test_weights(&self) -> Option<&[f32]> {
+        self.weight.map(|_| self.test_weights.as_slice())
+    }
+
+    ///Smallest and largest value of each input over the training and validation data, in the
+    /// scaled space the trees see.  The test data is left out so it cannot steer evolution
+    pub fn ranges(&self) -> Vec<(f32, f32)> {
+        self.ranges_over(&[&self.train, &self.validation])
+    }
+
+    ///Smallest and largest value of each input over every partition including the test data,
+    /// only meant for reporting on the final model
+    pub fn all_ranges(&self) -> Vec<(f32, f32)> {
+        self.ranges_over(&[&self.train, &self.validation, &self.test])
+    }
+
+    fn ranges_over(&self, partitions: &[&Vec<Vec<f32>>]) -> Vec<(f32, f32)> {
+        (0..self.dimensions)
+            .map(|j| {
+                partitions
+                    .iter()
+                    .filter(|partition| partition.len() > j)
+                    .flat_map(|partition| partition[j].iter())
+                    .fold((f32::INFINITY, f32::NEG_INFINITY), |(lo, hi), &x| {
+                        (lo.min(x), hi.max(x))
+                    })
+            })
+            .collect()
+    }
+
+    ///Names of the input variables, index i is the name of Input(i)
+    pub fn names(&self) -> &[String] {
+        &self.names
+    }
+
+    ///Name of the column holding the outputs to be predicted
+    pub fn target_name(&self) -> &str {
+        &self.columns[self.target]
+    }
 
     ///"constructor"
+    /// reads whitespace separated floats without a header, the last column is the target.
+    /// Panics on bad input, see try_new() for the error returning version
     pub fn new(dataset: &str, test_split: f32) -> Data {
-        let (train_data, test_data) = splitdata(dataset, test_split);
-        let dimension = train_data.len() - 1;
-        Data {
-            dimensions: dimension,
-            train: train_data,
-            test: test_data,
+        Data::try_new(dataset, test_split).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    ///Reads whitespace separated floats without a header, the last column is the target
+    pub fn try_new(dataset: &str, test_split: f32) -> Result<Data, DataError> {
+        let (_, rows) = read_rows(dataset, None, false)?;
+        let width = rows[0].len();
+        let mut columns: Vec<String> = (0..width - 1).map(|j| format!("x{}", j)).collect();
+        columns.push("y".to_string());
+        Data::from_rows(dataset, rows, columns, width - 1, test_split)
+    }
+
+    ///Load a delimited text file with an optional header row.
+    /// Without a header the columns are named x0, x1, ...
+    /// Panics on bad input, see try_from_csv() for the error returning version
+    pub fn from_csv(dataset: &str, options: &CsvOptions) -> Data {
+        Data::try_from_csv(dataset, options).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    ///Load a delimited text file with an optional header row.
+    /// Without a header the columns are named x0, x1, ...
+    pub fn try_from_csv(dataset: &str, options: &CsvOptions) -> Result<Data, DataError> {
+        let (header, rows) = read_rows(dataset, Some(options.delimiter), options.header)?;
+        let width = rows[0].len();
+        let columns: Vec<String> = match header {
+            Some(names) => names,
+            None => (0..width).map(|j| format!("x{}", j)).collect(),
+        };
+        let target = options
+            .target
+            .resolve(&columns)
+            .ok_or_else(|| DataError::MissingTarget {
+                file: dataset.to_string(),
+                target: format!("{:?}", options.target),
+            })?;
+        Data::from_rows(dataset, rows, columns, target, options.test_split)
+    }
+
+    fn from_rows(
+        dataset: &str,
+        rows: Vec<Vec<f32>>,
+        columns: Vec<String>,
+        target: usize,
+        test_split: f32,
+    ) -> Result<Data, DataError> {
+        if !(test_split > 0.0 && test_split < 1.0) {
+            return Err(DataError::SplitRange {
+                file: dataset.to_string(),
+                test_split,
+            });
+        }
+        let n = rows.len();
+        let n_test = ((n as f32) * test_split) as usize;
+        if n_test == 0 || n_test >= n {
+            return Err(DataError::EmptySplit {
+                file: dataset.to_string(),
+                partition: if n_test == 0 { "test" } else { "train" },
+                rows: n,
+                test_split,
+            });
+        }
+        let mut data = Data {
+            dimensions: columns.len() - 1,
+            name: dataset.to_string(),
+            rows: Arc::new(rows),
+            columns,
+            target,
+            targets: vec![target],
+            weight: None,
+            names: vec![],
+            test_split,
+            validation_split: 0.0,
+            chronological: false,
+            train_rows: vec![],
+            validation_rows: vec![],
+            test_rows: vec![],
+            feature_scaling: Scaling::None,
+            target_scaling: Scaling::None,
+            scales: vec![],
+            train: vec![],
+            validation: vec![],
+            test: vec![],
+            train_weights: vec![],
+            validation_weights: vec![],
+            test_weights: vec![],
+        };
+        data.split(&mut thread_rng());
+        Ok(data)
+    }
+
+    ///Build a dataset from series held in memory, one vector per column.
+    /// The target is the last series
+    pub fn from_series(
+        names: &[&str],
+        series: &[Vec<f32>],
+        test_split: f32,
+    ) -> Result<Data, DataError> {
+        let file = "series".to_string();
+        if names.len() != series.len() {
+            return Err(DataError::Names {
+                file,
+                names: names.len(),
+                series: series.len(),
+            });
+        }
+        let n = series.first().map(|s| s.len()).unwrap_or(0);
+        if n == 0 {
+            return Err(DataError::Empty { file });
+        }
+        for (j, column) in series.iter().enumerate() {
+            if column.len() != n {
+                return Err(DataError::SeriesLength {
+                    file,
+                    series: j + 1,
+                    expected: n,
+                    found: column.len(),
+                });
+            }
+        }
+        let rows: Vec<Vec<f32>> = (0..n)
+            .map(|i| series.iter().map(|s| s[i]).collect())
+            .collect();
+        let columns: Vec<String> = names.iter().map(|name| name.to_string()).collect();
+        Data::from_rows(&file, rows, columns, series.len() - 1, test_split)
+    }
+
+    ///Turn the rows, taken as time steps in file order, into a forecasting dataset.
+    /// Every column c gives the inputs c(t-1)..c(t-lags) and each target column at
+    /// time t+horizon-1 becomes an output, so horizon 1 predicts the next step.
+    /// The targets declared with set_targets() stay the targets, the active one stays active.
+    /// The split becomes chronological with the latest rows used for testing.
+    /// A weight column is not lagged, each row is weighted like its target time step.
+    /// Panics on bad input, see try_set_lags() for the error returning version
+    pub fn set_lags(self, lags: usize, horizon: usize) -> Data {
+        self.try_set_lags(lags, horizon)
+            .unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    ///Turn the rows into a forecasting dataset, see set_lags().
+    /// Fails if lags or horizon is zero or there are too few rows for them
+    pub fn try_set_lags(self, lags: usize, horizon: usize) -> Result<Data, DataError> {
+        let n = self.rows.len();
+        if lags == 0 || horizon == 0 || n <= lags + horizon {
+            return Err(DataError::Lags {
+                file: self.name.clone(),
+                rows: n,
+                lags,
+                horizon,
+            });
+        }
+        let lagged: Vec<usize> = (0..self.columns.len())
+            .filter(|&j| self.weight != Some(j))
+            .collect();
+        let mut columns: Vec<String> = vec![];
+        for &j in &lagged {
+            for l in 1..=lags {
+                columns.push(format!("{}(t-{})", self.columns[j], l));
+            }
+        }
+        if let Some(w) = self.weight {
+            columns.push(self.columns[w].clone());
+        }
+        for &k in &self.targets {
+            let name = &self.columns[k];
+            columns.push(match horizon {
+                1 => format!("{}(t)", name),
+                h => format!("{}(t+{})", name, h - 1),
+            });
+        }
+
+        let mut rows: Vec<Vec<f32>> = vec![];
+        for t in lags..=(n - horizon) {
+            let mut row: Vec<f32> = vec![];
+            for &j in &lagged {
+                for l in 1..=lags {
+                    row.push(self.rows[t - l][j]);
+                }
+            }
+            if let Some(w) = self.weight {
+                row.push(self.rows[t + horizon - 1][w]);
+            }
+            for &k in &self.targets {
+                row.push(self.rows[t + horizon - 1][k]);
+            }
+            rows.push(row);
+        }
+
+        //the outputs are the last columns, in the order the targets were declared
+        let first_output = columns.len() - self.targets.len();
+        let targets: Vec<usize> = (first_output..columns.len()).collect();
+        let active = self.targets.iter().position(|&k| k == self.target).unwrap();
+        let mut data =
+            Data::from_rows(&self.name, rows, columns, targets[active], self.test_split)?;
+        data.targets = targets;
+        data.validation_split = self.validation_split;
+        data.feature_scaling = self.feature_scaling;
+        data.target_scaling = self.target_scaling;
+        data.chronological = true;
+        data.weight = self.weight.map(|_| first_output - 1);
+        data.split(&mut thread_rng());
+        data.check_inputs()?;
+        Ok(data)
+    }
+
+    ///Keep the rows in file order when splitting instead of shuffling them,
+    /// the first rows train and the last rows test
+    pub fn set_chronological(mut self, chronological: bool) -> Data {
+        self.chronological = chronological;
+        self.split(&mut thread_rng());
+        self
+    }
+
+    ///Use a single column as the outputs to be predicted, all other columns become inputs.
+    /// Panics if the column does not exist
+    pub fn set_target(self, target: Column) -> Data {
+        self.set_targets(&[target])
+    }
+
+    ///Declare several columns as targets, none of them is used as an input.
+    /// The first one is active, see for_target() to switch to the others.
+    /// Panics if a column does not exist
+    pub fn set_targets(mut self, targets: &[Column]) -> Data {
+        assert!(!targets.is_empty(), "At least one target is needed");
+        self.targets = targets
+            .iter()
+            .map(|t| {
+                t.resolve(&self.columns)
+                    .unwrap_or_else(|| panic!("target column {:?} not found", t))
+            })
+            .collect();
+        self.target = self.targets[0];
+        self.rebuild();
+        self
+    }
+
+    ///Use a column as the weight of each row for weighted fitness metrics.
+    /// The column is no longer used as an input.  Panics if the column does not exist
+    /// or is a target
+    pub fn set_weights(mut self, column: Column) -> Data {
+        let weight = column
+            .resolve(&self.columns)
+            .unwrap_or_else(|| panic!("weight column {:?} not found", column));
+        assert!(
+            !self.targets.contains(&weight),
+            "A target cannot be the weight column"
+        );
+        self.weight = Some(weight);
+        self.rebuild();
+        self
+    }
+
+    ///Names of every column declared as a target
+    pub fn target_names(&self) -> Vec<&str> {
+        self.targets
+            .iter()
+            .map(|&j| self.columns[j].as_str())
+            .collect()
+    }
+
+    ///Copy of the data predicting the i-th declared target.
+    /// The split and the loaded rows are shared with this data
+    pub fn for_target(&self, i: usize) -> Data {
+        let mut data = self.clone();
+        data.target = self.targets[i];
+        data.rebuild();
+        data
+    }
+
+    ///Order in which the columns of the rows appear in train and test
+    fn layout(&self) -> Vec<usize> {
+        let mut layout: Vec<usize> = (0..self.columns.len())
+            .filter(|j| !self.targets.contains(j) && self.weight != Some(*j))
+            .collect();
+        layout.push(self.target);
+        layout
+    }
+
+    ///Hold out a fraction of all rows for validation, taken out of the training rows.
+    /// The rows are split again into train, validation and test
+    pub fn set_validation_split(mut self, validation_split: f32) -> Data {
+        let n = self.rows.len() as f32;
+        let n_test = (n * self.test_split) as usize;
+        let n_validation = (n * validation_split) as usize;
+        assert!(
+            n_validation > 0 && n_validation + n_test < self.rows.len(),
+            "Validation split of {} leaves a partition empty",
+            validation_split
+        );
+        self.validation_split = validation_split;
+        self.split(&mut thread_rng());
+        self
+    }
+
+    ///Rescale the input columns and the target column.  The parameters are fitted on the
+    /// training data and applied to every partition, so fitness is measured in scaled space
+    pub fn set_scaling(mut self, features: Scaling, target: Scaling) -> Data {
+        self.feature_scaling = features;
+        self.target_scaling = target;
+        self.rebuild();
+        self
+    }
+
+    ///Scaling parameters of each input column followed by the target column,
+    /// fitted on the training data
+    pub fn scales(&self) -> &[ColumnScale] {
+        &self.scales
+    }
+
+    ///True if any column is rescaled
+    pub fn is_scaled(&self) -> bool {
+        self.scales.iter().any(|scale| !scale.is_identity())
+    }
+
+    ///Shuffle and split the rows into training, validation and testing data again using the given rng.
+    /// Chronological data is split in order without shuffling
+    pub fn split<R: Rng + ?Sized>(&mut self, rng: &mut R) {
+        let (train_rows, validation_rows, test_rows) = splitdata(
+            self.rows.len(),
+            self.test_split,
+            self.validation_split,
+            !self.chronological,
+            rng,
+        );
+        self.train_rows = train_rows;
+        self.validation_rows = validation_rows;
+        self.test_rows = test_rows;
+        self.rebuild();
+    }
+
+    ///Collect the partitions from the rows, then fit the scaling on the training data
+    /// and apply it to every partition
+    fn rebuild(&mut self) {
+        let layout = self.layout();
+        self.dimensions = layout.len() - 1;
+        self.names = layout[..self.dimensions]
+            .iter()
+            .map(|&j| self.columns[j].clone())
+            .collect();
+        self.train = gather(&self.rows, &layout, &self.train_rows);
+        self.validation = gather(&self.rows, &layout, &self.validation_rows);
+        self.test = gather(&self.rows, &layout, &self.test_rows);
+        if let Some(w) = self.weight {
+            let weights = |indices: &[usize]| indices.iter().map(|&i| self.rows[i][w]).collect();
+            self.train_weights = weights(&self.train_rows);
+            self.validation_weights = weights(&self.validation_rows);
+            self.test_weights = weights(&self.test_rows);
+        }
+
+        self.scales = (0..layout.len())
+            .map(|j| {
+                let scaling = if j == self.dimensions {
+                    self.target_scaling
+                } else {
+                    self.feature_scaling
+                };
+                ColumnScale::fit(&self.train[j], scaling)
+            })
+            .collect();
+        for (j, scale) in self.scales.iter().enumerate() {
+            if scale.is_identity() {
+                continue;
+            }
+            for partition in [&mut self.train, &mut self.validation, &mut self.test] {
+                if let Some(column) = partition.get_mut(j) {
+                    column.iter_mut().for_each(|x| *x = scale.apply(*x));
+                }
+            }
         }
     }
+
+    ///Shuffle the rows and divide them into k folds.
+    /// Returns k copies of the data, where copy i tests on fold i and trains on the others.
+    /// A validation partition, if set, is taken from the end of the training rows of each copy.
+    /// Chronological data is not shuffled, so each fold is a contiguous block of time.
+    /// Panics if a fold would leave its train, validation or test partition empty
+    pub fn folds<R: Rng + ?Sized>(&self, k: usize, rng: &mut R) -> Vec<Data> {
+        let n = self.rows.len();
+        assert!(k >= 2 && k <= n, "Need between 2 and {} folds", n);
+        let mut indices: Vec<usize> = (0..n).collect();
+        if !self.chronological {
+            indices.shuffle(rng);
+        }
+
+        let mut folds: Vec<Data> = vec![];
+        for i in 0..k {
+            //fold i holds the rows from n*i/k up to n*(i+1)/k of the shuffled order
+            let start = n * i / k;
+            let end = n * (i + 1) / k;
+            let mut training: Vec<usize> = indices[..start].to_vec();
+            training.extend_from_slice(&indices[end..]);
+            let n_validation = ((training.len() as f32) * self.validation_split) as usize;
+            assert!(
+                n_validation < training.len() && (n_validation > 0 || self.validation_split == 0.0),
+                "A validation split of {} leaves a partition of fold {} empty for {} training rows",
+                self.validation_split,
+                i,
+                training.len()
+            );
+            let mut fold = self.clone();
+            //like splitdata the validation rows come after the training rows
+            fold.validation_rows = training.split_off(training.len() - n_validation);
+            fold.train_rows = training;
+            fold.test_rows = indices[start..end].to_vec();
+            fold.rebuild();
+            folds.push(fold);
+        }
+        folds
+    }
 }
 
 ///Private method to transpose the 2D Vector
 fn transpose_array(array: Vec<Vec<f32>>) -> Vec<Vec<f32>> {
     let mut result: Vec<Vec<f32>> = vec![];
+    if array.is_empty() {
+        return result;
+    }
     for j in 0..array[0].len() {
         let mut column = vec![];
-        for i in 0..array.len() {
-            column.push(array[i][j]);
+        for row in &array {
+            column.push(row[j]);
         }
         result.push(column);
     }
     result
 }
 
-///Primary helper method to parse and split the dataset
-/// Returns a tuple of the training and testing data
-fn splitdata(filename: &str, split: f32) -> (Vec<Vec<f32>>, Vec<Vec<f32>>) {
-    let reader = BufReader::new(File::open(filename).expect("Cannot open file"));
+///Split a line on the delimiter, or on whitespace if there is none
+fn split_line(line: &str, delimiter: Option<char>) -> Vec<&str> {
+    match delimiter {
+        Some(d) => line.split(d).map(|word| word.trim()).collect(),
+        None => line.split_whitespace().collect(),
+    }
+}
+
+///Optional header names and the rows of a parsed file
+type Parsed = (Option<Vec<String>>, Vec<Vec<f32>>);
+
+///Primary helper method to parse the dataset into rows.
+/// If header is set the first line is returned separately as the column names
+/// Every row must have the same number of columns as the first row
+fn read_rows(filename: &str, delimiter: Option<char>, header: bool) -> Result<Parsed, DataError> {
+    let io_error = |error| DataError::Io {
+        file: filename.to_string(),
+        error,
+    };
+    let reader = BufReader::new(File::open(filename).map_err(io_error)?);
+    let mut names: Option<Vec<String>> = None;
     let mut rows: Vec<Vec<f32>> = vec![];
-    for line in reader.lines() {
+    for (number, line) in reader.lines().enumerate() {
+        let line = line.map_err(io_error)?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let words = split_line(&line, delimiter);
+        if header && names.is_none() {
+            names = Some(
+                words
+                    .iter()
+                    .map(|w| w.trim_matches('"').to_string())
+                    .collect(),
+            );
+            continue;
+        }
+        let expected = names
+            .as_ref()
+            .map(|n| n.len())
+            .or_else(|| rows.first().map(|r| r.len()))
+            .unwrap_or(words.len());
+        if words.len() != expected {
+            return Err(DataError::Ragged {
+                file: filename.to_string(),
+                line: number + 1,
+                expected,
+                found: words.len(),
+            });
+        }
         let mut tmpy: Vec<f32> = Vec::new();
-        for word in line.unwrap().split_whitespace() {
-            let wordy = word.parse::<f32>().unwrap();
+        for (column, word) in words.iter().enumerate() {
+            let wordy = word.parse::<f32>().map_err(|_| DataError::Parse {
+                file: filename.to_string(),
+                line: number + 1,
+                column: column + 1,
+                token: word.to_string(),
+            })?;
             tmpy.push(wordy);
         }
-        if !&tmpy.is_empty() {
-            rows.push(tmpy.clone());
-        }
+        rows.push(tmpy);
+    }
+    if rows.is_empty() {
+        return Err(DataError::Empty {
+            file: filename.to_string(),
+        });
     }
-    let n = rows.len();
+    Ok((names, rows))
+}
+
+///Row indices of the training, validation and testing partitions of a dataset
+type Partitions = (Vec<usize>, Vec<usize>, Vec<usize>);
+
+///Primary helper method to split the dataset, in order unless shuffle is set
+/// Returns a tuple of the row indices of the training, validation and testing data
+fn splitdata<R: Rng + ?Sized>(
+    n: usize,
+    split: f32,
+    validation_split: f32,
+    shuffle: bool,
+    rng: &mut R,
+) -> Partitions {
     let n_test = ((n as f32) * split) as usize;
-    let n_train = n - n_test;
+    let n_validation = ((n as f32) * validation_split) as usize;
+    assert!(
+        n_test + n_validation <= n,
+        "Splits of {} and {} need more than {} rows",
+        split,
+        validation_split,
+        n
+    );
+    let n_train = n - n_test - n_validation;
     let mut indices: Vec<usize> = (0..n).collect();
-    indices.shuffle(&mut thread_rng());
-
-    let mut training: Vec<Vec<f32>> = vec![];
-    let mut testing: Vec<Vec<f32>> = vec![];
-    for i in (0..n) {
-        if i < n_train {
-            let tmp = indices[i];
-            let tmp2 = rows[tmp].clone();
-            training.push(tmp2);
-        } else {
-            let tmp = indices[i];
-            let tmp2 = rows[tmp].clone();
-            testing.push(tmp2);
-        }
-    }
-    let transposetrain = transpose_array(training);
-    let transposetest = transpose_array(testing);
-    (transposetrain, transposetest)
+    if shuffle {
+        indices.shuffle(rng);
+    }
+
+    let test = indices.split_off(n_train + n_validation);
+    let validation = indices.split_off(n_train);
+    (indices, validation, test)
+}
+
+///Collect the rows at the given indices, arranged in the layout and transposed to columns
+fn gather(rows: &[Vec<f32>], layout: &[usize], indices: &[usize]) -> Vec<Vec<f32>> {
+    let picked: Vec<Vec<f32>> = indices
+        .iter()
+        .map(|&i| layout.iter().map(|&j| rows[i][j]).collect())
+        .collect();
+    transpose_array(picked)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn single_series_is_lagged() {
+        let s: Vec<f32> = (1..=10).map(|i| i as f32).collect();
+        let data = Data::from_series(&["s"], &[s], 0.25).unwrap();
+        assert!(matches!(
+            data.check_inputs(),
+            Err(DataError::NoInputs { .. })
+        ));
+
+        let data = data.set_lags(2, 1);
+        assert_eq!(data.names(), ["s(t-1)", "s(t-2)"]);
+        assert_eq!(data.target_name(), "s(t)");
+        assert_eq!(data.train()[0], [2.0, 3.0, 4.0, 5.0, 6.0, 7.0]);
+        assert_eq!(data.train()[1], [1.0, 2.0, 3.0, 4.0, 5.0, 6.0]);
+        assert_eq!(data.train_targets(), &[3.0, 4.0, 5.0, 6.0, 7.0, 8.0]);
+        assert_eq!(data.test()[2], [9.0, 10.0]);
+    }
+
+    #[test]
+    fn chronological_folds_validate_on_latest_training_rows() {
+        let x: Vec<f32> = (0..8).map(|i| i as f32).collect();
+        let data = Data::from_series(&["x", "y"], &[x.clone(), x], 0.25)
+            .unwrap()
+            .set_chronological(true)
+            .set_validation_split(0.5);
+        let folds = data.folds(2, &mut thread_rng());
+        assert_eq!(folds[0].test()[0], [0.0, 1.0, 2.0, 3.0]);
+        assert_eq!(folds[0].train()[0], [4.0, 5.0]);
+        assert_eq!(folds[0].validation()[0], [6.0, 7.0]);
+        assert_eq!(folds[1].train()[0], [0.0, 1.0]);
+        assert_eq!(folds[1].validation()[0], [2.0, 3.0]);
+    }
 }
@@ -0,0 +1,247 @@
+use std::cmp::Ordering;
+
+use crate::base::functions::*;
+
+///Whether a fitness value is better when it is smaller or larger
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Minimize,
+    Maximize,
+}
+
+///A fitness metric comparing the outputs of a program with the expected outputs.
+/// Metrics are shared between the threads evaluating offspring
+pub trait Metric: Send + Sync {
+    ///Short name used when reporting the metric
+    fn name(&self) -> &str;
+
+    ///Whether the metric is minimized (errors) or maximized (scores)
+    fn direction(&self) -> Direction;
+
+    ///Calculate the fitness of the outputs x against the expected outputs y
+    fn evaluate(&self, x: &[f32], y: &[f32]) -> f32;
+
+    ///Calculate the fitness with a weight for each case.
+    /// Unweighted metrics ignore the weights
+    fn evaluate_weighted(&self, x: &[f32], y: &[f32], _w: &[f32]) -> f32 {
+        self.evaluate(x, y)
+    }
+
+    ///Calculate the fitness, using the weights if the data has any
+    fn score(&self, x: &[f32], y: &[f32], w: Option<&[f32]>) -> f32 {
+        match w {
+            Some(w) => self.evaluate_weighted(x, y, w),
+            None => self.evaluate(x, y),
+        }
+    }
+
+    ///Orders two fitness values so the better one comes first.
+    /// NaN is always ranked last regardless of direction
+    fn compare(&self, a: f32, b: f32) -> Ordering {
+        match (a.is_nan(), b.is_nan()) {
+            (true, true) => Ordering::Equal,
+            (true, false) => Ordering::Greater,
+            (false, true) => Ordering::Less,
+            (false, false) => match self.direction() {
+                Direction::Minimize => a.total_cmp(&b),
+                Direction::Maximize => b.total_cmp(&a),
+            },
+        }
+    }
+
+    ///Whether the outputs are linearly scaled to the targets before measuring fitness
+    fn linear_scaling(&self) -> bool {
+        false
+    }
+
+    ///True if fitness a is strictly better than fitness b
+    fn is_better(&self, a: f32, b: f32) -> bool {
+        self.compare(a, b) == Ordering::Less
+    }
+}
+
+///Root mean squared error
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Rmse;
+
+impl Metric for Rmse {
+    fn name(&self) -> &str {
+        "rmse"
+    }
+    fn direction(&self) -> Direction {
+        Direction::Minimize
+    }
+    fn evaluate(&self, x: &[f32], y: &[f32]) -> f32 {
+        root_mean_squared(x, y)
+    }
+}
+
+///Mean squared error
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Mse;
+
+impl Metric for Mse {
+    fn name(&self) -> &str {
+        "mse"
+    }
+    fn direction(&self) -> Direction {
+        Direction::Minimize
+    }
+    fn evaluate(&self, x: &[f32], y: &[f32]) -> f32 {
+        mean_squared(x, y)
+    }
+}
+
+///Mean absolute error
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Mae;
+
+impl Metric for Mae {
+    fn name(&self) -> &str {
+        "mae"
+    }
+    fn direction(&self) -> Direction {
+        Direction::Minimize
+    }
+    fn evaluate(&self, x: &[f32], y: &[f32]) -> f32 {
+        mean_absolute(x, y)
+    }
+}
+
+///Sum of absolute errors
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Sae;
+
+impl Metric for Sae {
+    fn name(&self) -> &str {
+        "sae"
+    }
+    fn direction(&self) -> Direction {
+        Direction::Minimize
+    }
+    fn evaluate(&self, x: &[f32], y: &[f32]) -> f32 {
+        sae(x, y)
+    }
+}
+
+///Root mean squared error normalized by the standard deviation of the targets
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Nrmse;
+
+impl Metric for Nrmse {
+    fn name(&self) -> &str {
+        "nrmse"
+    }
+    fn direction(&self) -> Direction {
+        Direction::Minimize
+    }
+    fn evaluate(&self, x: &[f32], y: &[f32]) -> f32 {
+        normalized_rmse(x, y)
+    }
+}
+
+///Coefficient of determination
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RSquared;
+
+impl Metric for RSquared {
+    fn name(&self) -> &str {
+        "r2"
+    }
+    fn direction(&self) -> Direction {
+        Direction::Maximize
+    }
+    fn evaluate(&self, x: &[f32], y: &[f32]) -> f32 {
+        r_squared(x, y)
+    }
+}
+
+///Pearson correlation coefficient
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Pearson;
+
+impl Metric for Pearson {
+    fn name(&self) -> &str {
+        "pearson"
+    }
+    fn direction(&self) -> Direction {
+        Direction::Maximize
+    }
+    fn evaluate(&self, x: &[f32], y: &[f32]) -> f32 {
+        pearson(x, y)
+    }
+}
+
+///Root mean squared error weighted by the sample weights of the data,
+/// unweighted if the data has none
+#[derive(Debug, Clone, Copy, Default)]
+pub struct WeightedRmse;
+
+impl Metric for WeightedRmse {
+    fn name(&self) -> &str {
+        "weighted rmse"
+    }
+    fn direction(&self) -> Direction {
+        Direction::Minimize
+    }
+    fn evaluate(&self, x: &[f32], y: &[f32]) -> f32 {
+        root_mean_squared(x, y)
+    }
+    fn evaluate_weighted(&self, x: &[f32], y: &[f32], w: &[f32]) -> f32 {
+        weighted_rmse(x, y, w)
+    }
+}
+
+///Mean absolute error weighted by the sample weights of the data,
+/// unweighted if the data has none
+#[derive(Debug, Clone, Copy, Default)]
+pub struct WeightedMae;
+
+impl Metric for WeightedMae {
+    fn name(&self) -> &str {
+        "weighted mae"
+    }
+    fn direction(&self) -> Direction {
+        Direction::Minimize
+    }
+    fn evaluate(&self, x: &[f32], y: &[f32]) -> f32 {
+        mean_absolute(x, y)
+    }
+    fn evaluate_weighted(&self, x: &[f32], y: &[f32], w: &[f32]) -> f32 {
+        weighted_mae(x, y, w)
+    }
+}
+
+///Any metric measured after Keijzer linear scaling: the intercept and slope fitting the
+/// training outputs to the training targets by least squares are applied to the outputs
+/// first, so evolution does not spend constants on the offset and scale of the targets
+#[derive(Debug, Clone)]
+pub struct LinearScaling<M: Metric> {
+    metric: M,
+    name: String,
+}
+
+impl<M: Metric> LinearScaling<M> {
+    pub fn new(metric: M) -> LinearScaling<M> {
+        let name = format!("scaled {}", metric.name());
+        LinearScaling { metric, name }
+    }
+}
+
+impl<M: Metric> Metric for LinearScaling<M> {
+    fn name(&self) -> &str {
+        &self.name
+    }
+    fn direction(&self) -> Direction {
+        self.metric.direction()
+    }
+    fn evaluate(&self, x: &[f32], y: &[f32]) -> f32 {
+        self.metric.evaluate(x, y)
+    }
+    fn evaluate_weighted(&self, x: &[f32], y: &[f32], w: &[f32]) -> f32 {
+        self.metric.evaluate_weighted(x, y, w)
+    }
+    fn linear_scaling(&self) -> bool {
+        true
+    }
+}
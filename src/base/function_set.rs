@@ -0,0 +1,330 @@
+use std::fmt;
+use std::sync::Arc;
+
+use rand::Rng;
+
+use crate::base::functions::{absolute, DIVISION_GUARD};
+use crate::base::individual::Node;
+use crate::base::interval::{node_interval, Interval};
+
+///Vectorized operation of a primitive, takes one vector of values per argument
+pub type Operation = dyn Fn(&[Vec<f32>]) -> Vec<f32> + Send + Sync;
+
+///A user-defined primitive that can be added to a FunctionSet without editing the Node enum.
+/// It appears in the chromosome as Node::Custom
+pub struct Primitive {
+    name: String,
+    arity: usize,
+    operation: Box<Operation>,
+    ///Printed form, in the style of the built-in symbols
+    symbol: String,
+}
+
+impl Primitive {
+    ///Create a primitive from a name, an arity of at least 1 and a vectorized operation.
+    /// It is printed as "name(" for arity 1 and "name" otherwise, like the built-ins
+    pub fn new<F>(name: &str, arity: usize, operation: F) -> Primitive
+    where
+        F: Fn(&[Vec<f32>]) -> Vec<f32> + Send + Sync + 'static,
+    {
+        assert!(arity > 0, "A primitive needs at least one argument");
+        Primitive {
+            name: name.to_string(),
+            arity,
+            operation: Box::new(operation),
+            symbol: match arity {
+                1 => format!("{}(", name),
+                _ => name.to_string(),
+            },
+        }
+    }
+
+    ///Set the printed form of the primitive
+    pub fn set_format(mut self, symbol: &str) -> Primitive {
+        self.symbol = symbol.to_string();
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+    pub fn arity(&self) -> usize {
+        self.arity
+    }
+    pub fn symbol(&self) -> &str {
+        &self.symbol
+    }
+
+    ///Apply the operation to the arguments
+    pub fn call(&self, args: &[Vec<f32>]) -> Vec<f32> {
+        let outputs = (self.operation)(args);
+        assert!(
+            outputs.len() == args[0].len(),
+            "Primitive {} returned the wrong number of outputs",
+            self.name
+        );
+        outputs
+    }
+}
+
+impl fmt::Debug for Primitive {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Primitive")
+            .field("name", &self.name)
+            .field("arity", &self.arity)
+            .finish()
+    }
+}
+
+///Primitives are the same only if they come from the same registration
+impl PartialEq for Primitive {
+    fn eq(&self, other: &Primitive) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+///How a primitive treats arguments outside its domain
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Protection {
+    ///Built-in semantics of the primitive: ln gives NaN below zero, / returns the numerator
+    /// for a zero denominator, sqrt and pow use the absolute value of their base
+    Default,
+    ///Apply the primitive to the absolute value of its first argument and clamp infinite
+    /// results, e.g. ln|x|
+    Absolute,
+    ///Return a fixed value wherever the result is undefined or infinite, and for
+    /// denominators near zero in division, e.g. x/0 = 1
+    Guard(f32),
+    ///Clamp infinite results to the largest finite values
+    Clamp,
+}
+
+///The function language of a run: which primitives initialization and mutation
+/// may draw, and how likely each one is to be drawn
+#[derive(Debug, Clone)]
+pub struct FunctionSet {
+    ///Enabled primitives with their selection weights, all weights are positive
+    primitives: Vec<(Node, f32)>,
+    ///Protection policies of primitives not using Protection::Default
+    protections: Vec<(Node, Protection)>,
+    ///Reject offspring not proven to stay inside the domain of every primitive
+    domain_check: bool,
+}
+
+impl Default for FunctionSet {
+    fn default() -> Self {
+        FunctionSet::new()
+    }
+}
+
+impl FunctionSet {
+    ///The default language: +, -, *, protected /, sin, ln and square with equal weights
+    pub fn new() -> FunctionSet {
+        FunctionSet::empty()
+            .enable(Node::Addition)
+            .enable(Node::Subtraction)
+            .enable(Node::Multiplication)
+            .enable(Node::Division)
+            .enable(Node::Sine)
+            .enable(Node::Ln)
+            .enable(Node::Square)
+    }
+
+    ///A language without primitives, to be filled with enable() and set_weight()
+    pub fn empty() -> FunctionSet {
+        FunctionSet {
+            primitives: vec![],
+            protections: vec![],
+            domain_check: false,
+        }
+    }
+
+    ///Every built-in primitive with equal weights
+    pub fn all() -> FunctionSet {
+        FunctionSet::new()
+            .enable(Node::Exp)
+            .enable(Node::Cosine)
+            .enable(Node::Tangent)
+            .enable(Node::Sqrt)
+            .enable(Node::Abs)
+            .enable(Node::Tanh)
+            .enable(Node::Min)
+            .enable(Node::Max)
+            .enable(Node::Power)
+            .enable(Node::GreaterThan)
+            .enable(Node::LessThan)
+            .enable(Node::Step)
+            .enable(Node::IfGreaterThanZero)
+    }
+
+    ///Enable a primitive with a selection weight of 1, or keep its weight if already enabled
+    pub fn enable(self, node: Node) -> FunctionSet {
+        match self.weight(&node) {
+            Some(_) => self,
+            None => self.set_weight(node, 1.0),
+        }
+    }
+
+    ///Register a user-defined primitive with a selection weight of 1.
+    /// A primitive registered earlier under the same name is replaced
+    pub fn register(mut self, primitive: Primitive) -> FunctionSet {
+        self.primitives
+            .retain(|(n, _)| !matches!(n, Node::Custom(p) if p.name() == primitive.name()));
+        self.set_weight(Node::Custom(Arc::new(primitive)), 1.0)
+    }
+
+    ///Node of a registered user-defined primitive, to pass to set_weight() or disable()
+    pub fn custom(&self, name: &str) -> Option<Node> {
+        self.primitives
+            .iter()
+            .find(|(n, _)| matches!(n, Node::Custom(p) if p.name() == name))
+            .map(|(n, _)| n.clone())
+    }
+
+    ///Remove a primitive from the language
+    pub fn disable(mut self, node: Node) -> FunctionSet {
+        self.primitives.retain(|(n, _)| *n != node);
+        self
+    }
+
+    ///Set how likely a primitive is to be drawn relative to the others.
+    /// A weight of 0 disables it, a missing primitive is enabled
+    pub fn set_weight(mut self, node: Node, weight: f32) -> FunctionSet {
+        assert!(node.arity() > 0, "{:?} is not a function", node);
+        assert!(weight >= 0.0, "Weight of {:?} is negative", node);
+        self = self.disable(node.clone());
+        if weight > 0.0 {
+            self.primitives.push((node, weight));
+        }
+        self
+    }
+
+    ///Selection weight of a primitive, None if it is not enabled
+    pub fn weight(&self, node: &Node) -> Option<f32> {
+        self.primitives
+            .iter()
+            .find(|(n, _)| n == node)
+            .map(|(_, w)| *w)
+    }
+
+    ///Set how a primitive treats arguments outside its domain
+    pub fn set_protection(mut self, node: Node, protection: Protection) -> FunctionSet {
+        assert!(node.arity() > 0, "{:?} is not a function", node);
+        self.protections.retain(|(n, _)| *n != node);
+        if protection != Protection::Default {
+            self.protections.push((node, protection));
+        }
+        self
+    }
+
+    ///Protect every enabled primitive so no output is NaN or infinite:
+    /// ln|x|, x/0 = 1 and clamping of infinities for the others.
+    /// Primitives enabled afterwards keep their default semantics
+    pub fn protected(self) -> FunctionSet {
+        let nodes: Vec<Node> = self.primitives.iter().map(|(n, _)| n.clone()).collect();
+        nodes.into_iter().fold(self, |set, node| {
+            let protection = match node {
+                Node::Ln => Protection::Absolute,
+                Node::Division => Protection::Guard(1.0),
+                _ => Protection::Clamp,
+            };
+            set.set_protection(node, protection)
+        })
+    }
+
+    ///Protection policy of a primitive
+    pub fn protection(&self, node: &Node) -> Protection {
+        self.protections
+            .iter()
+            .find(|(n, _)| n == node)
+            .map_or(Protection::Default, |(_, p)| *p)
+    }
+
+    ///Perform the operation of a primitive on its arguments under its protection policy
+    pub fn apply(&self, node: &Node, mut args: Vec<Vec<f32>>) -> Vec<f32> {
+        match self.protection(node) {
+            Protection::Default => node.operation(args),
+            Protection::Absolute => {
+                args[0] = absolute(args[0].to_vec());
+                clamp(node.operation(args))
+            }
+            Protection::Guard(value) => {
+                let guarded: Vec<bool> = match node {
+                    Node::Division => args[1].iter().map(|d| d.abs() <= DIVISION_GUARD).collect(),
+                    _ => vec![false; args[0].len()],
+                };
+                node.operation(args)
+                    .into_iter()
+                    .zip(guarded)
+                    .map(|(x, g)| if g || !x.is_finite() { value } else { x })
+                    .collect()
+            }
+            Protection::Clamp => clamp(node.operation(args)),
+        }
+    }
+
+    ///Range of the outputs of a primitive under its protection policy over the ranges of its
+    /// arguments, None if it may give NaN, an infinite value or hit a singularity
+    pub fn apply_interval(&self, node: &Node, mut args: Vec<Interval>) -> Option<Interval> {
+        match self.protection(node) {
+            Protection::Default => {
+                let (range, defined) = node_interval(node, &args);
+                (defined && range.is_finite()).then_some(range)
+            }
+            Protection::Absolute => {
+                args[0] = args[0].abs();
+                let (range, defined) = node_interval(node, &args);
+                defined.then(|| range.clamp())
+            }
+            Protection::Guard(value) => {
+                let (range, _) = node_interval(node, &args);
+                Some(range.clamp().hull(Interval::point(value)))
+            }
+            Protection::Clamp => {
+                let (range, defined) = node_interval(node, &args);
+                defined.then(|| range.clamp())
+            }
+        }
+    }
+
+    ///Make the variation operators reject offspring that interval analysis over the ranges
+    /// of the data cannot prove to stay inside the domain of every primitive.
+    /// Rejected offspring are replaced by their first parent, as for NaN fitness
+    pub fn set_domain_check(mut self, domain_check: bool) -> FunctionSet {
+        self.domain_check = domain_check;
+        self
+    }
+
+    pub fn domain_check(&self) -> bool {
+        self.domain_check
+    }
+
+    ///Enabled primitives with their selection weights
+    pub fn primitives(&self) -> &[(Node, f32)] {
+        &self.primitives
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.primitives.is_empty()
+    }
+
+    ///Draw a random primitive, each with probability proportional to its weight
+    pub fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> Node {
+        assert!(!self.is_empty(), "Function set is empty");
+        let total: f32 = self.primitives.iter().map(|(_, w)| w).sum();
+        let mut pick = rng.gen_range(0.0..total);
+        for (node, weight) in &self.primitives {
+            if pick < *weight {
+                return node.clone();
+            }
+            pick -= weight;
+        }
+        //only reachable through rounding, fall back to the last primitive
+        self.primitives[self.primitives.len() - 1].0.clone()
+    }
+}
+
+///Replace infinities with the largest finite values of the same sign
+fn clamp(x: Vec<f32>) -> Vec<f32> {
+    x.into_iter().map(|x| x.clamp(f32::MIN, f32::MAX)).collect()
+}
@@ -1,52 +1,157 @@
-use std::cmp;
+///Denominators with an absolute value up to this are treated as zero by division
+pub const DIVISION_GUARD: f32 = 0.00001;
 
 ///Takes as input a vector of f32's x containing outputs of tree on inputs
 /// as well as vector of correct expected outputs
 /// returns a float for root mean squared error
-pub fn root_mean_squared(x: &Vec<f32>, y: &Vec<f32>) -> f32 {
+pub fn root_mean_squared(x: &[f32], y: &[f32]) -> f32 {
+    mean_squared(x, y).sqrt()
+}
+
+///Takes as input a vector of f32's x containing outputs of tree on inputs
+/// as well as vector of correct expected outputs
+/// returns a float for mean squared error
+pub fn mean_squared(x: &[f32], y: &[f32]) -> f32 {
     assert!(x.len() == y.len());
 
     let numb = x.len() as f32;
-    let value = x
-        .into_iter()
-        .zip(y.into_iter())
-        .map(|(x, y)| ((x - y) as f32).powi(2));
+    let value = x.iter().zip(y).map(|(x, y)| (x - y).powi(2));
     let error_sum: f32 = value.sum();
-    (error_sum / numb).sqrt()
+    error_sum / numb
+}
+
+///Takes as input a vector of f32's x containing outputs of tree on inputs
+/// as well as vector of correct expected outputs
+/// returns a float for mean absolute error
+pub fn mean_absolute(x: &[f32], y: &[f32]) -> f32 {
+    sae(x, y) / x.len() as f32
 }
 
 ///Takes as input a vector of f32's x containing outputs of tree on inputs
 /// as well as vector of correct expected outputs
 /// returns a float for sum of absolute errors
-pub fn sae(x: &Vec<f32>, y: &Vec<f32>) -> f32 {
+pub fn sae(x: &[f32], y: &[f32]) -> f32 {
     assert!(x.len() == y.len());
-    let n = x.len() as f32;
-    let value = x
-        .into_iter()
-        .zip(y.into_iter())
-        .map(|(x, y)| ((x - y) as f32).abs());
+    let value = x.iter().zip(y).map(|(x, y)| (x - y).abs());
     let sae: f32 = value.sum();
     sae
 }
 
+///Root mean squared error with each squared error weighted by w
+pub fn weighted_rmse(x: &[f32], y: &[f32], w: &[f32]) -> f32 {
+    assert!(x.len() == y.len() && x.len() == w.len());
+    let error_sum: f32 = x
+        .iter()
+        .zip(y)
+        .zip(w)
+        .map(|((x, y), w)| w * (x - y).powi(2))
+        .sum();
+    (error_sum / w.iter().sum::<f32>()).sqrt()
+}
+
+///Mean absolute error with each absolute error weighted by w
+pub fn weighted_mae(x: &[f32], y: &[f32], w: &[f32]) -> f32 {
+    assert!(x.len() == y.len() && x.len() == w.len());
+    let error_sum: f32 = x
+        .iter()
+        .zip(y)
+        .zip(w)
+        .map(|((x, y), w)| w * (x - y).abs())
+        .sum();
+    error_sum / w.iter().sum::<f32>()
+}
+
+///Root mean squared error normalized by the standard deviation of the expected outputs
+pub fn normalized_rmse(x: &[f32], y: &[f32]) -> f32 {
+    root_mean_squared(x, y) / variance(y).sqrt()
+}
+
+///Coefficient of determination of the outputs x against the expected outputs y
+pub fn r_squared(x: &[f32], y: &[f32]) -> f32 {
+    assert!(x.len() == y.len());
+    let numb = y.len() as f32;
+    let ss_res: f32 = x.iter().zip(y).map(|(x, y)| (y - x).powi(2)).sum();
+    let ss_tot = variance(y) * numb;
+    1.0 - ss_res / ss_tot
+}
+
+///Pearson correlation coefficient between the outputs x and the expected outputs y
+/// a program with constant output has no correlation and scores 0
+pub fn pearson(x: &[f32], y: &[f32]) -> f32 {
+    assert!(x.len() == y.len());
+    let mean_x = mean(x);
+    let mean_y = mean(y);
+    let mut cov = 0.0;
+    let mut var_x = 0.0;
+    let mut var_y = 0.0;
+    for (a, b) in x.iter().zip(y) {
+        cov += (a - mean_x) * (b - mean_y);
+        var_x += (a - mean_x).powi(2);
+        var_y += (b - mean_y).powi(2);
+    }
+    if var_x == 0.0 || var_y == 0.0 {
+        return 0.0;
+    }
+    cov / (var_x.sqrt() * var_y.sqrt())
+}
+
+///Least squares line through the points (x, y), returns (intercept, slope).
+/// A constant x gets a slope of 0 and the mean of y as intercept
+pub fn linear_fit(x: &[f32], y: &[f32]) -> (f32, f32) {
+    assert!(x.len() == y.len());
+    let mean_x = mean(x);
+    let mean_y = mean(y);
+    let mut cov = 0.0;
+    let mut var_x = 0.0;
+    for (a, b) in x.iter().zip(y) {
+        cov += (a - mean_x) * (b - mean_y);
+        var_x += (a - mean_x).powi(2);
+    }
+    if var_x == 0.0 {
+        return (mean_y, 0.0);
+    }
+    let slope = cov / var_x;
+    (mean_y - slope * mean_x, slope)
+}
+
+///Arithmetic mean of a vector
+pub fn mean(x: &[f32]) -> f32 {
+    x.iter().sum::<f32>() / x.len() as f32
+}
+
+///Population variance of a vector
+pub fn variance(x: &[f32]) -> f32 {
+    let avg = mean(x);
+    x.iter().map(|x| (x - avg).powi(2)).sum::<f32>() / x.len() as f32
+}
+
+///Median of the values, NaN for no values
+pub fn median(x: &[f32]) -> f32 {
+    if x.is_empty() {
+        return f32::NAN;
+    }
+    let mut sorted = x.to_vec();
+    sorted.sort_by(|a, b| a.total_cmp(b));
+    let mid = sorted.len() / 2;
+    if sorted.len().is_multiple_of(2) {
+        (sorted[mid - 1] + sorted[mid]) / 2.0
+    } else {
+        sorted[mid]
+    }
+}
+
 ///Element by element addition between two vectors
 /// returns vector of outputs
 pub fn add(x: Vec<f32>, y: Vec<f32>) -> Vec<f32> {
     assert!(x.len() == y.len());
-    x.into_iter()
-        .zip(y.into_iter())
-        .map(|(x, y)| x + y)
-        .collect()
+    x.into_iter().zip(y).map(|(x, y)| x + y).collect()
 }
 
 ///Element by element subtraction between two vectors
 /// returns vector of outputs
 pub fn subtract(x: Vec<f32>, y: Vec<f32>) -> Vec<f32> {
     assert!(x.len() == y.len());
-    x.into_iter()
-        .zip(y.into_iter())
-        .map(|(x, y)| x - y)
-        .collect()
+    x.into_iter().zip(y).map(|(x, y)| x - y).collect()
 }
 
 ///Element by element product between two vectors
@@ -54,7 +159,7 @@ pub fn subtract(x: Vec<f32>, y: Vec<f32>) -> Vec<f32> {
 pub fn multiply(x: Vec<f32>, y: Vec<f32>) -> Vec<f32> {
     assert!(x.len() == y.len());
     x.into_iter() // or rayon::prelude::par_iter()
-        .zip(y.into_iter())
+        .zip(y)
         .map(|(x, y)| x * y)
         .collect()
 }
@@ -64,19 +169,16 @@ pub fn multiply(x: Vec<f32>, y: Vec<f32>) -> Vec<f32> {
 pub fn divide(x: Vec<f32>, y: Vec<f32>) -> Vec<f32> {
     assert!(x.len() == y.len());
 
-    let min = 0.00001;
+    let min = DIVISION_GUARD;
     let protected_division = |(num, denom): (f32, f32)| -> f32 {
         // numerator and denominator
         if denom.abs() > min {
-            return num / denom;
+            num / denom
         } else {
-            return num;
+            num
         }
     };
-    x.into_iter()
-        .zip(y.into_iter())
-        .map(protected_division)
-        .collect()
+    x.into_iter().zip(y).map(protected_division).collect()
 }
 ///Element by element sine function calculation
 /// returns a vector of outputs
@@ -96,3 +198,100 @@ pub fn square(x: Vec<f32>) -> Vec<f32> {
     let e = |x: f32| -> f32 { x.powf(2.0) };
     x.into_iter().map(e).collect()
 }
+
+///Element by element exponential calculation
+/// returns a vector of outputs
+pub fn exp(x: Vec<f32>) -> Vec<f32> {
+    x.into_iter().map(|x| x.exp()).collect()
+}
+
+///Element by element cosine function calculation
+/// returns a vector of outputs
+pub fn cosine(x: Vec<f32>) -> Vec<f32> {
+    x.into_iter().map(|x| x.cos()).collect()
+}
+
+///Element by element tangent function calculation
+/// returns a vector of outputs
+pub fn tangent(x: Vec<f32>) -> Vec<f32> {
+    x.into_iter().map(|x| x.tan()).collect()
+}
+
+///Element by element protected square root, the root of the absolute value
+/// returns a vector of outputs
+pub fn sqrt(x: Vec<f32>) -> Vec<f32> {
+    x.into_iter().map(|x| x.abs().sqrt()).collect()
+}
+
+///Element by element absolute value
+/// returns a vector of outputs
+pub fn absolute(x: Vec<f32>) -> Vec<f32> {
+    x.into_iter().map(|x| x.abs()).collect()
+}
+
+///Element by element hyperbolic tangent calculation
+/// returns a vector of outputs
+pub fn tanh(x: Vec<f32>) -> Vec<f32> {
+    x.into_iter().map(|x| x.tanh()).collect()
+}
+
+///Element by element minimum of two vectors
+/// returns vector of outputs
+pub fn minimum(x: Vec<f32>, y: Vec<f32>) -> Vec<f32> {
+    assert!(x.len() == y.len());
+    x.into_iter().zip(y).map(|(x, y)| x.min(y)).collect()
+}
+
+///Element by element maximum of two vectors
+/// returns vector of outputs
+pub fn maximum(x: Vec<f32>, y: Vec<f32>) -> Vec<f32> {
+    assert!(x.len() == y.len());
+    x.into_iter().zip(y).map(|(x, y)| x.max(y)).collect()
+}
+
+///Element by element protected power, the absolute value of x raised to y
+/// so negative bases never give NaN
+/// returns vector of outputs
+pub fn power(x: Vec<f32>, y: Vec<f32>) -> Vec<f32> {
+    assert!(x.len() == y.len());
+    x.into_iter().zip(y).map(|(x, y)| x.abs().powf(y)).collect()
+}
+
+///Element by element comparison, 1 where x is greater than y and 0 otherwise
+/// returns vector of outputs
+pub fn greater_than(x: Vec<f32>, y: Vec<f32>) -> Vec<f32> {
+    assert!(x.len() == y.len());
+    x.into_iter()
+        .zip(y)
+        .map(|(x, y)| if x > y { 1.0 } else { 0.0 })
+        .collect()
+}
+
+///Element by element comparison, 1 where x is less than y and 0 otherwise
+/// returns vector of outputs
+pub fn less_than(x: Vec<f32>, y: Vec<f32>) -> Vec<f32> {
+    assert!(x.len() == y.len());
+    x.into_iter()
+        .zip(y)
+        .map(|(x, y)| if x < y { 1.0 } else { 0.0 })
+        .collect()
+}
+
+///Heaviside step of each element, 1 where x is positive and 0 otherwise
+/// returns vector of outputs
+pub fn step(x: Vec<f32>) -> Vec<f32> {
+    x.into_iter()
+        .map(|x| if x > 0.0 { 1.0 } else { 0.0 })
+        .collect()
+}
+
+///Element by element conditional, the value of then where cond is positive
+/// and the value of otherwise elsewhere
+/// returns vector of outputs
+pub fn if_greater_than_zero(cond: Vec<f32>, then: Vec<f32>, otherwise: Vec<f32>) -> Vec<f32> {
+    assert!(cond.len() == then.len() && cond.len() == otherwise.len());
+    cond.into_iter()
+        .zip(then.into_iter().zip(otherwise))
+        .map(|(c, (t, o))| if c > 0.0 { t } else { o })
+        .collect()
+}
@@ -1,14 +1,26 @@
 extern crate rand;
 use std::fs::File;
 use std::io::prelude::*;
+use std::path::Path;
 
-use rand::{thread_rng, Rng};
+use rand::rngs::StdRng;
+use rand::{Rng, RngCore, SeedableRng};
+use rayon::prelude::*;
+use rayon::{ThreadPool, ThreadPoolBuilder};
 
 use crate::base::data::Data;
+use crate::base::fitness::{Metric, Rmse};
+use crate::base::function_set::FunctionSet;
+use crate::base::functions::{mean, variance};
 use crate::base::individual::genetics;
 use crate::base::individual::Individual;
-use crate::base::individual::Node;
+use crate::base::island::{Island, Migration, Topology};
+use crate::base::optimize::Tuning;
+use crate::base::pareto::{
+    non_dominated_sort, objectives, survivors, Complexity, CrowdedTournament,
+};
 use crate::base::pop::Population;
+use crate::base::selection::{Selection, Tournament};
 
 ///Gp struct containing the algorithm state
 pub struct Gp {
@@ -17,21 +29,198 @@ pub struct Gp {
     mut_chance: f32,
     population: Population,
     population_size: usize,
-    tournament_size: usize,
+    selection: Box<dyn Selection>,
+    replacement: Replacement,
+    ///Number of best individuals carried over unchanged, or protected from replacement
+    elitism: usize,
+    ///Individuals evaluated so far in the run
+    evaluations: usize,
+    ///Sub-populations of an island model run, see evolve_islands()
+    islands: Vec<Island>,
+    migration: Migration,
+    ///Whether the best model is algebraically simplified before it is tested and printed
+    simplify: bool,
+    ///Which models have their constants tuned and the iteration budget of each tuning
+    tuning: Option<(Tuning, usize)>,
+    metric: Box<dyn Metric>,
+    functions: FunctionSet,
+    rng: StdRng,
+    ///Threads breeding offspring, rayon's global pool if None
+    pool: Option<ThreadPool>,
+    output: String,
+    ///Best model of the run, chosen by validation fitness if the data has a validation partition
+    best: Option<Individual>,
+}
+
+///How offspring enter the population
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Replacement {
+    ///Every generation a new population of offspring replaces the old one
+    Generational,
+    ///Each offspring immediately replaces the loser of an inverse tournament of the given
+    /// size.  A generation is then a number of offspring equal to the population size
+    SteadyState(usize),
+}
+
+///Train and test fitness of the best individual evolved on one fold
+#[derive(Debug, Clone, Copy)]
+pub struct FoldResult {
+    pub train: f32,
+    pub test: f32,
+}
+
+///Best model evolved for one of the targets of the data
+#[derive(Debug, Clone)]
+pub struct TargetResult {
+    pub target: String,
+    pub best: Individual,
+}
+
+///Per-fold results of a k-fold cross-validation
+#[derive(Debug, Clone)]
+pub struct CrossValidation {
+    pub folds: Vec<FoldResult>,
+}
+
+impl CrossValidation {
+    fn trains(&self) -> Vec<f32> {
+        self.folds.iter().map(|f| f.train).collect()
+    }
+    fn tests(&self) -> Vec<f32> {
+        self.folds.iter().map(|f| f.test).collect()
+    }
+    ///Mean training fitness over the folds
+    pub fn train_mean(&self) -> f32 {
+        mean(&self.trains())
+    }
+    ///Standard deviation of the training fitness over the folds
+    pub fn train_std(&self) -> f32 {
+        variance(&self.trains()).sqrt()
+    }
+    ///Mean test fitness over the folds
+    pub fn test_mean(&self) -> f32 {
+        mean(&self.tests())
+    }
+    ///Standard deviation of the test fitness over the folds
+    pub fn test_std(&self) -> f32 {
+        variance(&self.tests()).sqrt()
+    }
 }
 
 impl Gp {
+    ///Panics if the data has no input columns, see Data::check_inputs()
     pub fn new(datas: Data) -> Gp {
+        datas.check_inputs().unwrap_or_else(|e| panic!("{}", e));
         Gp {
             dataset: datas,
             cross_chance: 0.8,
             mut_chance: 0.2,
             population: Population::new(),
             population_size: 200,
-            tournament_size: 3,
+            selection: Box::new(Tournament::new(3)),
+            replacement: Replacement::Generational,
+            elitism: 0,
+            evaluations: 0,
+            islands: vec![],
+            simplify: false,
+            tuning: None,
+            migration: Migration {
+                interval: 10,
+                count: 1,
+                topology: Topology::Ring,
+            },
+            metric: Box::new(Rmse),
+            functions: FunctionSet::new(),
+            rng: StdRng::from_entropy(),
+            pool: None,
+            output: "results.txt".to_string(),
+            best: None,
         }
     }
 
+    ///Set the method used to choose parents, a tournament of size 3 by default
+    pub fn set_selection<S: Selection + 'static>(mut self, selection: S) -> Gp {
+        self.selection = Box::new(selection);
+        self
+    }
+
+    ///Set how offspring enter the population, generational by default
+    pub fn set_replacement(mut self, replacement: Replacement) -> Gp {
+        self.replacement = replacement;
+        self
+    }
+
+    ///Copy the best k individuals of each generation into the next unchanged.
+    /// With steady-state replacement they are never chosen to be replaced
+    pub fn set_elitism(mut self, k: usize) -> Gp {
+        self.elitism = k;
+        self
+    }
+
+    ///Set the number of threads breeding and evaluating offspring, all cores by default.
+    /// Every offspring draws from its own generator seeded by the generator of the run,
+    /// so a seeded run gives the same results with any number of threads
+    pub fn set_threads(mut self, threads: usize) -> Gp {
+        let pool = ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build()
+            .expect("failed to build thread pool");
+        self.pool = Some(pool);
+        self
+    }
+
+    ///Algebraically simplify the best model of the run before it is tested and printed,
+    /// see Individual::simplify()
+    pub fn set_simplify(mut self, simplify: bool) -> Gp {
+        self.simplify = simplify;
+        self
+    }
+
+    ///Tune the constants of the best fraction of every generation or of the final model with
+    /// Levenberg-Marquardt, for at most iterations steps per model, see Individual::optimize_constants()
+    pub fn set_tuning(mut self, tuning: Tuning, iterations: usize) -> Gp {
+        self.tuning = Some((tuning, iterations));
+        self
+    }
+
+    ///Add an island for evolve_islands()
+    pub fn add_island(mut self, island: Island) -> Gp {
+        self.islands.push(island);
+        self
+    }
+
+    ///Add n islands with the population size and crossover chance of the Gp and a tournament
+    /// of size 3.  The parameters are read when evolve_islands() starts, so set_pop() and
+    /// set_cross_chance() may come before or after this
+    pub fn set_islands(mut self, n: usize) -> Gp {
+        for _ in 0..n {
+            self.islands.push(Island::inheriting());
+        }
+        self
+    }
+
+    ///Every interval generations each island sends copies of its best count individuals to the
+    /// islands given by the topology, where they replace the worst.  Every 10 generations one
+    /// individual along a ring by default
+    pub fn set_migration(mut self, interval: usize, count: usize, topology: Topology) -> Gp {
+        assert!(interval > 0, "Migration interval must be positive");
+        self.migration = Migration {
+            interval,
+            count,
+            topology,
+        };
+        self
+    }
+
+    pub fn islands(&self) -> &[Island] {
+        &self.islands
+    }
+
+    ///Number of individuals evaluated so far, including the initial population
+    pub fn evaluations(&self) -> usize {
+        self.evaluations
+    }
+
     ///Set the population size
     pub fn set_pop(mut self, pop_size: usize) -> Gp {
         self.population_size = pop_size;
@@ -40,11 +229,21 @@ impl Gp {
 
     /// Initialization of population with ramped half and half generation with initial max depth of 5
     pub fn init_pop(&mut self) {
-        self.population = Population::ramped(self.population_size, 5, &self.dataset);
+        self.population = Population::ramped(
+            self.population_size,
+            5,
+            &self.dataset,
+            &self.functions,
+            self.metric.as_ref(),
+            &mut self.rng,
+        );
+        self.population.fit_sort(self.metric.as_ref());
+        self.evaluations = self.population.size();
     }
 
+    ///Use tournament selection with the given tournament size
     pub fn set_tourn_size(mut self, tourn: usize) -> Gp {
-        self.tournament_size = tourn;
+        self.selection = Box::new(Tournament::new(tourn));
         self
     }
 
@@ -58,20 +257,72 @@ impl Gp {
         self
     }
 
+    ///Seed the random number generator driving the whole run so it can be reproduced.
+    /// The dataset is split again with the seeded generator
+    pub fn set_seed(mut self, seed: u64) -> Gp {
+        self.rng = StdRng::seed_from_u64(seed);
+        self.dataset.split(&mut self.rng);
+        self
+    }
+
+    ///Set the fitness metric used for evaluation and selection (root mean squared error by default)
+    pub fn set_metric<M: Metric + 'static>(mut self, metric: M) -> Gp {
+        self.metric = Box::new(metric);
+        self
+    }
+
+    ///Set the function language initialization and mutation draw primitives from
+    pub fn set_functions(mut self, functions: FunctionSet) -> Gp {
+        assert!(!functions.is_empty(), "Function set is empty");
+        self.functions = functions;
+        self
+    }
+
+    ///Set the file the results are written to ("results.txt" by default)
+    pub fn set_output(mut self, output: &str) -> Gp {
+        self.output = output.to_string();
+        self
+    }
+
+    ///Best model of the run, chosen by validation fitness if the data has a validation
+    /// partition and by training fitness otherwise.  Only this model is evaluated on the test data
+    pub fn best(&self) -> Option<&Individual> {
+        self.best.as_ref()
+    }
+
+    ///Replace the best model of the run if the best of the current population beats it
+    fn update_best(&mut self) {
+        let candidate = self.population.first_getter();
+        let fitness = |ind: &Individual| {
+            if self.dataset.has_validation() {
+                ind.validation_fit().unwrap()
+            } else {
+                ind.train_fit().unwrap()
+            }
+        };
+        let replace = match &self.best {
+            None => true,
+            Some(best) => self.metric.is_better(fitness(candidate), fitness(best)),
+        };
+        if replace {
+            self.best = Some(candidate.clone());
+        }
+    }
+
     ///Method to print the current state of the fitnesses for an individual
     fn print_state(&self, file: &mut File) {
         let ind = self.population.first_getter();
         let tmp_pop = self.population.get_pop();
         let mut fitnesses = vec![];
         for i in tmp_pop {
-            let fit = (i.train_fit().unwrap());
+            let fit = i.train_fit().unwrap();
             if !fit.is_nan() && !fit.is_infinite() {
                 fitnesses.push(fit);
             }
         }
         let sum: f32 = Iterator::sum(fitnesses.iter());
         let mean = sum / fitnesses.len() as f32;
-        let line = format!("----------------------");
+        let line = "----------------------".to_string();
         println!("{}", line);
         writeln!(file, "{}", line).expect("write failed");
         let line = format!("train:\t{:?}", ind.train_fit().unwrap());
@@ -80,9 +331,14 @@ impl Gp {
         let line = format!("training mean:\t{:?}", mean);
         println!("{}", line);
         writeln!(file, "{}", line).expect("write failed");
-        let line = format!("test:\t{:?}", ind.test_fit().unwrap());
+        let line = format!("evaluations:\t{:?}", self.evaluations);
         println!("{}", line);
         writeln!(file, "{}", line).expect("write failed");
+        if let Some(fit) = ind.validation_fit() {
+            let line = format!("validation:\t{:?}", fit);
+            println!("{}", line);
+            writeln!(file, "{}", line).expect("write failed");
+        }
         let line = format!("size:\t{:?}", ind.size());
         println!("{}", line);
         writeln!(file, "{}", line).expect("write failed");
@@ -94,42 +350,479 @@ impl Gp {
     ///Core evolution loop
     pub fn evolve(&mut self, numb_gens: usize) {
         // I/O
-        let mut file = File::create("results.txt").expect("create failed");
+        let mut file = File::create(&self.output).expect("create failed");
 
-        let mut rng = thread_rng();
         if self.population.size() == 0 {
             self.init_pop();
         }
 
         for x in 0..numb_gens {
-            let mut children = Population::new();
             println!("Generation: {}", x + 1);
-            while children.size() < self.population.size() {
-                let child: Individual;
-                let mut parent1: Individual = Individual::new();
-                while parent1.train_fit().is_none() || parent1.train_fit().unwrap().is_nan() {
-                    parent1 = self.population.tournament(self.tournament_size);
-                }
-                let chance: f32 = rng.gen();
-                if chance < self.cross_chance {
-                    let mut parent2: Individual = Individual::new();
-                    while parent2.train_fit().is_none() || parent2.train_fit().unwrap().is_nan() {
-                        parent2 = self.population.tournament(self.tournament_size);
-                    }
-                    let parent2 = self.population.tournament(self.tournament_size);
-                    child = genetics::subtree_cross(&parent1, &parent2, &self.dataset);
-                } else {
-                    child = genetics::single_point_mut(&parent1, &self.dataset);
+            match self.replacement {
+                Replacement::Generational => self.generation(),
+                Replacement::SteadyState(size) => self.steady_state(size),
+            }
+            self.tune();
+            self.update_best();
+            self.print_state(&mut file);
+        }
+        self.print_best(&mut file);
+    }
+
+    ///Island model evolution.  Each island evolves independently with its own parameters and
+    /// the best individuals migrate between them periodically, see set_migration().
+    /// The state of every island and the global best are logged each generation
+    pub fn evolve_islands(&mut self, numb_gens: usize) {
+        assert!(!self.islands.is_empty(), "No islands, see set_islands()");
+        let mut file = File::create(&self.output).expect("create failed");
+        let mut islands = std::mem::take(&mut self.islands);
+
+        let mut evaluations = 0;
+        for island in islands.iter_mut() {
+            island.population_size.get_or_insert(self.population_size);
+            island.cross_chance.get_or_insert(self.cross_chance);
+            if island.population.size() == 0 {
+                self.swap_island(island);
+                self.init_pop();
+                self.swap_island(island);
+            }
+            evaluations += island.population.size();
+        }
+        self.evaluations = evaluations;
+
+        for x in 0..numb_gens {
+            println!("Generation: {}", x + 1);
+            for (i, island) in islands.iter_mut().enumerate() {
+                self.swap_island(island);
+                match self.replacement {
+                    Replacement::Generational => self.generation(),
+                    Replacement::SteadyState(size) => self.steady_state(size),
                 }
-                children.insert(child);
+                self.tune();
+                self.update_best();
+                let line = format!("island {}", i + 1);
+                println!("{}", line);
+                writeln!(file, "{}", line).expect("write failed");
+                self.print_state(&mut file);
+                self.swap_island(island);
+            }
+            if (x + 1) % self.migration.interval == 0 {
+                self.migrate(&mut islands);
+            }
+            let best = self.best.as_ref().expect("no generations evolved");
+            let line = format!("global best:\t{:?}", best.train_fit().unwrap());
+            println!("{}", line);
+            writeln!(file, "{}", line).expect("write failed");
+            if let Some(fit) = best.validation_fit() {
+                let line = format!("global best validation:\t{:?}", fit);
+                println!("{}", line);
+                writeln!(file, "{}", line).expect("write failed");
+            }
+            let line = format!("evaluations:\t{:?}\n\n", self.evaluations);
+            println!("{}", line);
+            writeln!(file, "{}", line).expect("write failed");
+        }
+        self.islands = islands;
+        self.print_best(&mut file);
+    }
+
+    ///Multi-objective evolution with NSGA-II, minimizing the training error and the complexity
+    /// of the models.  Parents are chosen by crowded binary tournament and the next population
+    /// keeps the best fronts of parents and offspring together.  At the end the Pareto front of
+    /// the final population is evaluated on the test data, written to the output file after
+    /// the best model and returned, simplest model first
+    pub fn evolve_pareto(&mut self, numb_gens: usize, complexity: Complexity) -> Vec<Individual> {
+        let mut file = File::create(&self.output).expect("create failed");
+
+        if self.population.size() == 0 {
+            self.init_pop();
+        }
+        let mut selection: Box<dyn Selection> = Box::new(CrowdedTournament::new(complexity));
+        std::mem::swap(&mut self.selection, &mut selection);
+
+        for x in 0..numb_gens {
+            println!("Generation: {}", x + 1);
+            self.selection
+                .prepare(&self.population, self.metric.as_ref(), &mut self.rng);
+            let mut combined = self.population.get_pop();
+            combined.extend(self.offspring(self.population.size()));
+            let scores: Vec<(f32, f32)> = combined
+                .iter()
+                .map(|i| objectives(i, self.metric.as_ref(), complexity))
+                .collect();
+            let mut next = Population::new();
+            for i in survivors(&scores, self.population.size()) {
+                next.insert(combined[i].clone());
             }
-            self.population = children;
-            self.population.fit_sort();
+            self.population = next;
+            self.population.fit_sort(self.metric.as_ref());
+            self.tune();
+            self.update_best();
             self.print_state(&mut file);
-            if x == numb_gens - 1 {
-                let ind = self.population.first_getter().clone();
-                ind.print_nodes(&mut file);
+        }
+        std::mem::swap(&mut self.selection, &mut selection);
+        self.print_best(&mut file);
+
+        let front = self.pareto_front(complexity);
+        let line = "======================\npareto front".to_string();
+        println!("{}", line);
+        writeln!(file, "{}", line).expect("write failed");
+        for ind in &front {
+            let depth = match complexity {
+                Complexity::Size => String::new(),
+                Complexity::Depth => format!("\tdepth: {}", ind.depth()),
+            };
+            let line = format!(
+                "size: {}{}\ttrain: {:?}\ttest: {:?}",
+                ind.size(),
+                depth,
+                ind.train_fit().unwrap(),
+                ind.test_fit().unwrap()
+            );
+            println!("{}", line);
+            writeln!(file, "{}", line).expect("write failed");
+            ind.print_nodes(&mut file, self.dataset.names());
+        }
+        front
+    }
+
+    ///Non-dominated individuals of the population evaluated on the test data, one per
+    /// distinct pair of objectives, simplest first
+    fn pareto_front(&self, complexity: Complexity) -> Vec<Individual> {
+        let individuals = self.population.individuals();
+        let scores: Vec<(f32, f32)> = individuals
+            .iter()
+            .map(|i| objectives(i, self.metric.as_ref(), complexity))
+            .collect();
+        let mut front = non_dominated_sort(&scores).swap_remove(0);
+        front.sort_by(|&a, &b| scores[a].1.total_cmp(&scores[b].1));
+        front.dedup_by(|a, b| scores[*a] == scores[*b]);
+        front
+            .into_iter()
+            .map(|i| {
+                let mut ind = individuals[i].clone();
+                ind.evaluate_test(&self.dataset, &self.functions, self.metric.as_ref());
+                ind
+            })
+            .collect()
+    }
+
+    ///Exchange the population and parameters of the Gp with those of an island
+    fn swap_island(&mut self, island: &mut Island) {
+        std::mem::swap(&mut self.population, &mut island.population);
+        let unresolved = "island parameters are set when evolve_islands() starts";
+        std::mem::swap(
+            &mut self.population_size,
+            island.population_size.as_mut().expect(unresolved),
+        );
+        std::mem::swap(
+            &mut self.cross_chance,
+            island.cross_chance.as_mut().expect(unresolved),
+        );
+        std::mem::swap(&mut self.selection, &mut island.selection);
+    }
+
+    ///Send copies of the best individuals of every island along the routes of the topology,
+    /// all migrants are chosen before any island receives
+    fn migrate(&mut self, islands: &mut [Island]) {
+        let routes = self.migration.topology.routes(islands.len(), &mut self.rng);
+        let emigrants: Vec<Vec<Individual>> = islands
+            .iter()
+            .map(|island| island.population.get_best(self.migration.count))
+            .collect();
+        for (from, to) in routes {
+            for migrant in &emigrants[from] {
+                let population = &mut islands[to].population;
+                let worst = population.size() - 1;
+                population.replace(worst, migrant.clone(), self.metric.as_ref());
+            }
+        }
+    }
+
+    ///Replace the population with offspring, keeping the elite
+    fn generation(&mut self) {
+        self.selection
+            .prepare(&self.population, self.metric.as_ref(), &mut self.rng);
+        let mut children = Population::new();
+        children.insert_multiple(self.population.get_best(self.elitism));
+        let needed = self.population.size().saturating_sub(children.size());
+        children.insert_multiple(self.offspring(needed));
+        self.population = children;
+        self.population.fit_sort(self.metric.as_ref());
+    }
+
+    ///Tune the constants of the best fraction of the population in parallel if Tuning::Top
+    /// is set, the population is sorted again afterwards.  Every evaluation of a tree during
+    /// tuning counts towards the evaluations of the run
+    fn tune(&mut self) {
+        let (fraction, iterations) = match self.tuning {
+            Some((Tuning::Top(fraction), iterations)) => (fraction, iterations),
+            _ => return,
+        };
+        let n = ((fraction * self.population.size() as f32).ceil() as usize)
+            .min(self.population.size());
+        let (datas, functions, metric) = (&self.dataset, &self.functions, self.metric.as_ref());
+        let population = &mut self.population;
+        let mut tune_all = || -> usize {
+            population.mut_pop()[..n]
+                .par_iter_mut()
+                .map(|ind| {
+                    let evaluations;
+                    (*ind, evaluations) =
+                        ind.optimize_constants(datas, functions, metric, iterations);
+                    evaluations
+                })
+                .sum()
+        };
+        self.evaluations += match &self.pool {
+            Some(pool) => pool.install(tune_all),
+            None => tune_all(),
+        };
+        self.population.fit_sort(self.metric.as_ref());
+    }
+
+    ///Breed n offspring in parallel, each with its own seed
+    fn offspring(&mut self, n: usize) -> Vec<Individual> {
+        let seeds: Vec<u64> = (0..n).map(|_| self.rng.gen()).collect();
+        let this = &*self;
+        let breed_all = || -> Vec<Individual> {
+            seeds
+                .par_iter()
+                .map(|&seed| this.breed(&mut StdRng::seed_from_u64(seed)))
+                .collect()
+        };
+        let offspring = match &self.pool {
+            Some(pool) => pool.install(breed_all),
+            None => breed_all(),
+        };
+        self.evaluations += offspring.len();
+        offspring
+    }
+
+    ///Breed as many offspring as the population size, each replacing the loser of an
+    /// inverse tournament as soon as it is created.  The selection is prepared again before
+    /// every offspring since each replacement reorders the population
+    fn steady_state(&mut self, size: usize) {
+        for _ in 0..self.population.size() {
+            self.selection
+                .prepare(&self.population, self.metric.as_ref(), &mut self.rng);
+            let seed: u64 = self.rng.gen();
+            let child = self.breed(&mut StdRng::seed_from_u64(seed));
+            self.evaluations += 1;
+            let loser = self
+                .population
+                .inverse_tournament(size, self.elitism, &mut self.rng);
+            self.population.replace(loser, child, self.metric.as_ref());
+        }
+    }
+
+    ///Select parents and create one evaluated offspring with crossover or mutation
+    fn breed(&self, rng: &mut dyn RngCore) -> Individual {
+        let child: Individual;
+        let mut parent1: Individual = Individual::new();
+        while parent1.train_fit().is_none() || parent1.train_fit().unwrap().is_nan() {
+            parent1 = self
+                .selection
+                .select(&self.population, self.metric.as_ref(), rng);
+        }
+        let chance: f32 = rng.gen();
+        if chance < self.cross_chance {
+            let mut parent2: Individual = Individual::new();
+            while parent2.train_fit().is_none() || parent2.train_fit().unwrap().is_nan() {
+                parent2 = self
+                    .selection
+                    .select(&self.population, self.metric.as_ref(), rng);
             }
+            child = genetics::subtree_cross(
+                &parent1,
+                &parent2,
+                &self.dataset,
+                &self.functions,
+                self.metric.as_ref(),
+                rng,
+            );
+        } else {
+            child = genetics::single_point_mut(
+                &parent1,
+                &self.dataset,
+                &self.functions,
+                self.metric.as_ref(),
+                rng,
+            );
         }
+        child
+    }
+
+    ///Evaluate the best model of the run on the test data and print it
+    fn print_best(&mut self, file: &mut File) {
+        let mut ind = match self.best.take() {
+            Some(ind) => ind,
+            None => return,
+        };
+        if let Some((Tuning::Final, iterations)) = self.tuning {
+            let evaluations;
+            (ind, evaluations) = ind.optimize_constants(
+                &self.dataset,
+                &self.functions,
+                self.metric.as_ref(),
+                iterations,
+            );
+            self.evaluations += evaluations;
+        }
+        if self.simplify {
+            ind = ind.simplify(&self.dataset, &self.functions, self.metric.as_ref());
+        }
+        ind.evaluate_test(&self.dataset, &self.functions, self.metric.as_ref());
+        let line = "======================\nbest model".to_string();
+        println!("{}", line);
+        writeln!(file, "{}", line).expect("write failed");
+        let line = format!("train:\t{:?}", ind.train_fit().unwrap());
+        println!("{}", line);
+        writeln!(file, "{}", line).expect("write failed");
+        if let Some(fit) = ind.validation_fit() {
+            let line = format!("validation:\t{:?}", fit);
+            println!("{}", line);
+            writeln!(file, "{}", line).expect("write failed");
+        }
+        let line = format!("test:\t{:?}", ind.test_fit().unwrap());
+        println!("{}", line);
+        writeln!(file, "{}", line).expect("write failed");
+        let line = format!("size:\t{:?}", ind.size());
+        println!("{}", line);
+        writeln!(file, "{}", line).expect("write failed");
+        let line = format!("depth:\t{:?}", ind.depth());
+        println!("{}", line);
+        writeln!(file, "{}", line).expect("write failed");
+        //the test data has been used by now, so the reported range covers it too
+        let line = match ind.output_range_over(&self.dataset.all_ranges(), &self.functions) {
+            Some(range) => format!("output range:\t{}", range),
+            None => "output range:\tunbounded".to_string(),
+        };
+        println!("{}", line);
+        writeln!(file, "{}", line).expect("write failed");
+        ind.print_nodes(file, self.dataset.names());
+        if self.dataset.is_scaled() {
+            let line = "in original units:".to_string();
+            println!("{}", line);
+            writeln!(file, "{}", line).expect("write failed");
+            ind.to_original_units(&self.dataset)
+                .print_nodes(file, self.dataset.names());
+        }
+        self.best = Some(ind);
+    }
+
+    ///K-fold cross-validation.  The data is divided into k folds and evolution runs once per
+    /// fold, testing on that fold and training on the rest.  Each run writes its log to the
+    /// output file prefixed with the fold number, the summary goes to the output file itself
+    pub fn cross_validate(&mut self, k: usize, numb_gens: usize) -> CrossValidation {
+        let folds = self.dataset.folds(k, &mut self.rng);
+
+        let mut results = CrossValidation { folds: vec![] };
+        for (i, fold) in folds.into_iter().enumerate() {
+            println!("Fold: {}", i + 1);
+            let best = self.evolve_on(fold, &format!("fold{}", i + 1), numb_gens);
+            results.folds.push(FoldResult {
+                train: best.train_fit().unwrap(),
+                test: best.test_fit().unwrap(),
+            });
+        }
+
+        let mut file = File::create(&self.output).expect("create failed");
+        for (i, fold) in results.folds.iter().enumerate() {
+            let line = format!(
+                "fold {}:\ttrain: {:?}\ttest: {:?}",
+                i + 1,
+                fold.train,
+                fold.test
+            );
+            println!("{}", line);
+            writeln!(file, "{}", line).expect("write failed");
+        }
+        let line = format!(
+            "train mean:\t{:?}\tstd: {:?}",
+            results.train_mean(),
+            results.train_std()
+        );
+        println!("{}", line);
+        writeln!(file, "{}", line).expect("write failed");
+        let line = format!(
+            "test mean:\t{:?}\tstd: {:?}",
+            results.test_mean(),
+            results.test_std()
+        );
+        println!("{}", line);
+        writeln!(file, "{}", line).expect("write failed");
+        results
+    }
+
+    ///Evolve one model per target declared in the data (see Data::set_targets()).
+    /// Each run writes its log to the output file prefixed with the target name,
+    /// the per-target summary goes to the output file itself
+    pub fn evolve_targets(&mut self, numb_gens: usize) -> Vec<TargetResult> {
+        let names: Vec<String> = self
+            .dataset
+            .target_names()
+            .iter()
+            .map(|name| name.to_string())
+            .collect();
+
+        let mut results = vec![];
+        for (i, name) in names.into_iter().enumerate() {
+            println!("Target: {}", name);
+            let dataset = self.dataset.for_target(i);
+            let prefix: String = name
+                .chars()
+                .map(|c| if c.is_alphanumeric() { c } else { '_' })
+                .collect();
+            let best = self.evolve_on(dataset, &prefix, numb_gens);
+            results.push(TargetResult { target: name, best });
+        }
+
+        let mut file = File::create(&self.output).expect("create failed");
+        for (i, result) in results.iter().enumerate() {
+            let line = format!(
+                "target {}:\ttrain: {:?}\ttest: {:?}",
+                result.target,
+                result.best.train_fit().unwrap(),
+                result.best.test_fit().unwrap()
+            );
+            println!("{}", line);
+            writeln!(file, "{}", line).expect("write failed");
+            let dataset = self.dataset.for_target(i);
+            result.best.print_nodes(&mut file, dataset.names());
+            if dataset.is_scaled() {
+                let line = "in original units:".to_string();
+                println!("{}", line);
+                writeln!(file, "{}", line).expect("write failed");
+                result
+                    .best
+                    .to_original_units(&dataset)
+                    .print_nodes(&mut file, dataset.names());
+            }
+        }
+        results
+    }
+
+    ///Evolve a fresh population on other data, logging to the output file with a prefix.
+    /// Returns the best model of that run, the data and output of the Gp are left unchanged
+    fn evolve_on(&mut self, dataset: Data, prefix: &str, numb_gens: usize) -> Individual {
+        let path = Path::new(&self.output);
+        let name = path
+            .file_name()
+            .expect("output is not a file")
+            .to_string_lossy();
+        let output = path
+            .with_file_name(format!("{}_{}", prefix, name))
+            .to_string_lossy()
+            .to_string();
+        let dataset = std::mem::replace(&mut self.dataset, dataset);
+        let output = std::mem::replace(&mut self.output, output);
+        self.population = Population::new();
+        self.best = None;
+        self.evolve(numb_gens);
+        self.dataset = dataset;
+        self.output = output;
+        self.population = Population::new();
+        self.best.take().expect("no generations evolved")
     }
 }
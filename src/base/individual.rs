@@ -1,12 +1,17 @@
 use std::fs::File;
 use std::io::prelude::*;
+use std::sync::Arc;
 
 use crate::base::data::Data;
+use crate::base::fitness::Metric;
+use crate::base::function_set::{FunctionSet, Primitive};
 use crate::base::functions::*;
-use rand::prelude::SliceRandom;
-use rand::{thread_rng, Rng};
+use crate::base::interval::Interval;
+use crate::base::optimize::levenberg_marquardt;
+use crate::base::simplify;
+use rand::Rng;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 
 /// Node Enum representing a primitive of an Individual
 pub enum Node {
@@ -17,6 +22,22 @@ pub enum Node {
     Sine,
     Ln,
     Square,
+    Exp,
+    Cosine,
+    Tangent,
+    Sqrt,
+    Abs,
+    Tanh,
+    Min,
+    Max,
+    Power,
+    GreaterThan,
+    LessThan,
+    Step,
+    ///Conditional taking (cond, then, else)
+    IfGreaterThanZero,
+    ///User-defined primitive registered in a FunctionSet
+    Custom(Arc<Primitive>),
     Input(usize),
     Constant(f32),
 }
@@ -28,7 +49,18 @@ impl Node {
         match *self {
             Node::Input(_) => 0,
             Node::Constant(_) => 0,
-            Node::Sine | Node::Ln | Node::Square => 1,
+            Node::Sine
+            | Node::Ln
+            | Node::Square
+            | Node::Exp
+            | Node::Cosine
+            | Node::Tangent
+            | Node::Sqrt
+            | Node::Abs
+            | Node::Tanh
+            | Node::Step => 1,
+            Node::IfGreaterThanZero => 3,
+            Node::Custom(ref primitive) => primitive.arity(),
             _ => 2,
         }
     }
@@ -46,16 +78,31 @@ impl Node {
             Node::Square => square(args[0].to_vec()),
             Node::Multiplication => multiply(args[0].to_vec(), args[1].to_vec()),
             Node::Division => divide(args[0].to_vec(), args[1].to_vec()),
+            Node::Exp => exp(args[0].to_vec()),
+            Node::Cosine => cosine(args[0].to_vec()),
+            Node::Tangent => tangent(args[0].to_vec()),
+            Node::Sqrt => sqrt(args[0].to_vec()),
+            Node::Abs => absolute(args[0].to_vec()),
+            Node::Tanh => tanh(args[0].to_vec()),
+            Node::Min => minimum(args[0].to_vec(), args[1].to_vec()),
+            Node::Max => maximum(args[0].to_vec(), args[1].to_vec()),
+            Node::Power => power(args[0].to_vec(), args[1].to_vec()),
+            Node::GreaterThan => greater_than(args[0].to_vec(), args[1].to_vec()),
+            Node::LessThan => less_than(args[0].to_vec(), args[1].to_vec()),
+            Node::Step => step(args[0].to_vec()),
+            Node::IfGreaterThanZero => {
+                if_greater_than_zero(args[0].to_vec(), args[1].to_vec(), args[2].to_vec())
+            }
+            Node::Custom(ref primitive) => primitive.call(&args),
             _ => panic!("Non-functional node.  Cannot perform operation."),
         }
     }
 
     ///Return a f32 constant Node in range of -1.0 and 1.0
-    pub fn get_ephemeral() -> Node {
-        let mut rng = thread_rng();
+    pub fn get_ephemeral<R: Rng + ?Sized>(rng: &mut R) -> Node {
         let mut set_const: Vec<f32> = vec![];
         set_const.push(-1.0);
-        for i in 0..8 {
+        for _ in 0..8 {
             let tmp = rng.gen_range(-0.9..=0.9);
             set_const.push(tmp);
         }
@@ -64,25 +111,37 @@ impl Node {
         Node::Constant(set_const[k])
     }
 
-    ///Returns a random function from the set of functions defined in the Node enum.
-    pub fn get_function() -> Node {
-        let mut rng = thread_rng();
-        let i: usize = rng.gen_range(0..6);
-        match i {
-            0 => Node::Addition,
-            1 => Node::Subtraction,
-            2 => Node::Sine,
-            3 => Node::Multiplication,
-            4 => Node::Division,
-            5 => Node::Ln,
-            6 => Node::Square,
-            _ => panic!("Node function does not exist"),
+    ///Printed form of the node, inputs are shown by their variable name
+    pub fn symbol(&self, names: &[String]) -> String {
+        match *self {
+            Node::Addition => "+".to_string(),
+            Node::Subtraction => "-".to_string(),
+            Node::Sine => "sin(".to_string(),
+            Node::Multiplication => "*".to_string(),
+            Node::Division => "/".to_string(),
+            Node::Ln => "ln(".to_string(),
+            Node::Square => "square(".to_string(),
+            Node::Exp => "exp(".to_string(),
+            Node::Cosine => "cos(".to_string(),
+            Node::Tangent => "tan(".to_string(),
+            Node::Sqrt => "sqrt(".to_string(),
+            Node::Abs => "abs(".to_string(),
+            Node::Tanh => "tanh(".to_string(),
+            Node::Min => "min".to_string(),
+            Node::Max => "max".to_string(),
+            Node::Power => "pow".to_string(),
+            Node::GreaterThan => "gt".to_string(),
+            Node::LessThan => "lt".to_string(),
+            Node::Step => "step(".to_string(),
+            Node::IfGreaterThanZero => "ifgtz".to_string(),
+            Node::Custom(ref primitive) => primitive.symbol().to_string(),
+            Node::Constant(x) => format!("({})", x),
+            Node::Input(j) => names[j].clone(),
         }
     }
 
     ///pick a random variable for input
-    pub fn get_random_input(dimensions: usize) -> Node {
-        let mut rng = thread_rng();
+    pub fn get_random_input<R: Rng + ?Sized>(dimensions: usize, rng: &mut R) -> Node {
         let i: usize = rng.gen_range(0..dimensions);
         Node::Input(i)
     }
@@ -96,16 +155,31 @@ pub struct Individual {
     chromosome: Vec<Node>,
     ///Training data output
     training_outputs: Option<Vec<f32>>,
-    ///Test data output
+    ///Validation data output
+    validation_outputs: Option<Vec<f32>>,
+    ///Test data output, only calculated once at the end of a run
     test_outputs: Option<Vec<f32>>,
     ///Fitness value (error) for training data
     train_fitness: Option<f32>,
+    ///Fitness value (error) for validation data
+    validation_fitness: Option<f32>,
     ///Fitness value (error) for testing data
     test_fitness: Option<f32>,
     ///Number of elements in tree
     size: Option<usize>,
     ///Tree depth
     depth: Option<usize>,
+    ///Absolute error on each training case, after linear scaling
+    case_errors: Option<Vec<f32>>,
+    ///Intercept and slope applied to the outputs of the tree, set by eval_fitness()
+    /// when the metric uses linear scaling
+    linear_scaling: Option<(f32, f32)>,
+}
+
+impl Default for Individual {
+    fn default() -> Self {
+        Individual::new()
+    }
 }
 
 impl Individual {
@@ -118,12 +192,17 @@ impl Individual {
         self.train_fitness
     }
 
-    ///fitness for testing data
+    ///fitness for validation data, None if the data has no validation partition
+    pub fn validation_fit(&self) -> Option<f32> {
+        self.validation_fitness
+    }
+
+    ///fitness for testing data, None until evaluate_test() is called
     pub fn test_fit(&self) -> Option<f32> {
         self.test_fitness
     }
     pub fn size(&self) -> usize {
-        if self.chromosome.len() > 0 {
+        if !self.chromosome.is_empty() {
             self.chromosome.len()
         } else {
             self.size.expect("Vector empty")
@@ -133,20 +212,31 @@ impl Individual {
         self.depth.expect("Not computed")
     }
 
+    ///Outputs of the tree on the training data, before any linear scaling
     pub fn training_outputs(&self) -> Vec<f32> {
         self.training_outputs
             .clone()
             .expect("Training data outputs not calculated")
     }
 
+    ///Absolute error on each training case, used by lexicase selection
+    pub fn case_errors(&self) -> &[f32] {
+        self.case_errors
+            .as_deref()
+            .expect("Training errors not calculated")
+    }
+
+    ///Outputs of the model on the test data, with the linear scaling applied
     pub fn test_outputs(&self) -> Vec<f32> {
         self.test_outputs
             .clone()
             .expect("Test data outputs not calculated")
     }
 
-    pub fn outputs(&self) -> (Vec<f32>, Vec<f32>) {
-        (self.training_outputs(), self.test_outputs())
+    ///Outputs on the training and test data, the test outputs are None until the model
+    /// has been tested
+    pub fn outputs(&self) -> (Vec<f32>, Option<Vec<f32>>) {
+        (self.training_outputs(), self.test_outputs.clone())
     }
 
     ///Method to initialize an empty Individual
@@ -154,44 +244,61 @@ impl Individual {
         Individual {
             chromosome: vec![],
             training_outputs: None,
+            validation_outputs: None,
             test_outputs: None,
             train_fitness: None,
+            validation_fitness: None,
             test_fitness: None,
             size: None,
             depth: None,
+            case_errors: None,
+            linear_scaling: None,
         }
     }
 
     ///Create individual with grow method.
     ///Generate an expression where each leaf might have a different depth
-    pub fn grow(depth_max: usize, data_object: &Data) -> Individual {
+    pub fn grow<R: Rng + ?Sized>(
+        depth_max: usize,
+        data_object: &Data,
+        functions: &FunctionSet,
+        rng: &mut R,
+    ) -> Individual {
         let mut x: Individual = Individual::new();
-        x.grow_recurse(0, depth_max, data_object.dimensions());
+        x.grow_recurse(0, depth_max, data_object.dimensions(), functions, rng);
         x
     }
 
     ///Recursive method for grow method
-    fn grow_recurse(&mut self, depth: usize, depth_max: usize, dimensions: usize) {
-        let mut rng = thread_rng();
+    fn grow_recurse<R: Rng + ?Sized>(
+        &mut self,
+        depth: usize,
+        depth_max: usize,
+        dimensions: usize,
+        functions: &FunctionSet,
+        rng: &mut R,
+    ) {
         if depth == depth_max {
             if rng.gen::<bool>() {
-                self.chromosome.push(Node::get_ephemeral());
+                self.chromosome.push(Node::get_ephemeral(rng));
             } else {
-                self.chromosome.push(Node::get_random_input(dimensions));
+                self.chromosome
+                    .push(Node::get_random_input(dimensions, rng));
             }
         } else {
             if rng.gen::<bool>() {
-                let new_nodey = Node::get_function();
+                let new_nodey = functions.sample(rng);
                 let arity = new_nodey.arity();
                 self.chromosome.push(new_nodey);
                 for _ in 0..arity {
-                    self.grow_recurse(depth + 1, depth_max, dimensions);
+                    self.grow_recurse(depth + 1, depth_max, dimensions, functions, rng);
                 }
             } else {
                 if rng.gen::<bool>() {
-                    self.chromosome.push(Node::get_ephemeral());
+                    self.chromosome.push(Node::get_ephemeral(rng));
                 } else {
-                    self.chromosome.push(Node::get_random_input(dimensions));
+                    self.chromosome
+                        .push(Node::get_random_input(dimensions, rng));
                 }
             }
         }
@@ -199,27 +306,39 @@ impl Individual {
 
     ///Create individual with full method.
     ///Generate an expression where each leaf has the same depth
-    pub fn full(depth_max: usize, data_object: &Data) -> Individual {
+    pub fn full<R: Rng + ?Sized>(
+        depth_max: usize,
+        data_object: &Data,
+        functions: &FunctionSet,
+        rng: &mut R,
+    ) -> Individual {
         let mut x: Individual = Individual::new();
-        x.full_recurse(0, depth_max, data_object.dimensions());
+        x.full_recurse(0, depth_max, data_object.dimensions(), functions, rng);
         x
     }
 
     ///Recursive method for full method
-    fn full_recurse(&mut self, depth: usize, depth_max: usize, dimensions: usize) {
-        let mut rng = thread_rng();
+    fn full_recurse<R: Rng + ?Sized>(
+        &mut self,
+        depth: usize,
+        depth_max: usize,
+        dimensions: usize,
+        functions: &FunctionSet,
+        rng: &mut R,
+    ) {
         if depth == depth_max {
             if rng.gen::<bool>() {
-                self.chromosome.push(Node::get_ephemeral());
+                self.chromosome.push(Node::get_ephemeral(rng));
             } else {
-                self.chromosome.push(Node::get_random_input(dimensions));
+                self.chromosome
+                    .push(Node::get_random_input(dimensions, rng));
             }
         } else {
-            let new_nodey = Node::get_function();
+            let new_nodey = functions.sample(rng);
             let arity = new_nodey.arity();
             self.chromosome.push(new_nodey);
             for _ in 0..arity {
-                self.full_recurse(depth + 1, depth_max, dimensions);
+                self.full_recurse(depth + 1, depth_max, dimensions, functions, rng);
             }
         }
     }
@@ -247,39 +366,160 @@ impl Individual {
         }
     }
 
-    ///Return a vector of results for all data
-    fn get_outputs(&mut self, mut index: usize, data: &Vec<Vec<f32>>) -> Vec<f32> {
-        let nodey = &self.get_node(index);
-        match nodey {
-            &Node::Constant(numb) => vec![numb; data[0].len()],
-            &Node::Input(x) => data[x].to_vec(),
+    ///Return a vector of results for all data.
+    /// index is advanced past the subtree so each argument starts after the previous one
+    fn get_outputs(
+        &mut self,
+        index: &mut usize,
+        data: &Vec<Vec<f32>>,
+        functions: &FunctionSet,
+    ) -> Vec<f32> {
+        let nodey = &self.get_node(*index);
+        *index += 1;
+        match *nodey {
+            Node::Constant(numb) => vec![numb; data[0].len()],
+            Node::Input(x) => data[x].to_vec(),
             _ => {
                 let mut arguments: Vec<Vec<f32>> = vec![];
                 for _ in 0..nodey.arity() {
-                    index += 1;
-                    arguments.push(self.get_outputs(index, data));
+                    arguments.push(self.get_outputs(index, data, functions));
                 }
-                nodey.operation(arguments)
+                functions.apply(nodey, arguments)
+            }
+        }
+    }
+
+    ///Guaranteed range of the outputs over the ranges of the inputs in the training and
+    /// validation data, found with interval arithmetic.  None if the tree may apply a primitive
+    /// outside its domain (ln of a possibly non-positive argument, division by an interval
+    /// containing zero) or give an infinite value
+    pub fn output_range(&self, datas: &Data, functions: &FunctionSet) -> Option<Interval> {
+        self.output_range_over(&datas.ranges(), functions)
+    }
+
+    ///Guaranteed range of the outputs over the given (smallest, largest) value of each input,
+    /// see output_range()
+    pub fn output_range_over(
+        &self,
+        ranges: &[(f32, f32)],
+        functions: &FunctionSet,
+    ) -> Option<Interval> {
+        let ranges: Vec<Interval> = ranges
+            .iter()
+            .map(|&(lo, hi)| Interval::new(lo, hi))
+            .collect();
+        let range = self.interval(&mut 0, &ranges, functions)?;
+        match self.linear_scaling {
+            Some((intercept, slope)) => {
+                let ends = [
+                    intercept + slope * range.lower,
+                    intercept + slope * range.upper,
+                ];
+                Some(Interval::new(ends[0].min(ends[1]), ends[0].max(ends[1])))
+            }
+            None => Some(range),
+        }
+    }
+
+    ///Recursive method for output_range, index is advanced past the subtree like get_outputs
+    fn interval(
+        &self,
+        index: &mut usize,
+        ranges: &[Interval],
+        functions: &FunctionSet,
+    ) -> Option<Interval> {
+        let nodey = &self.chromosome[*index];
+        *index += 1;
+        match *nodey {
+            Node::Constant(numb) => Some(Interval::point(numb)),
+            Node::Input(x) => Some(ranges[x]),
+            _ => {
+                let mut arguments: Vec<Interval> = vec![];
+                for _ in 0..nodey.arity() {
+                    arguments.push(self.interval(index, ranges, functions)?);
+                }
+                functions.apply_interval(nodey, arguments)
+            }
+        }
+    }
+
+    ///Calculate the outputs for the training and validation data.
+    /// The test data is left untouched until evaluate_test()
+    pub fn outputs_calculate(&mut self, datas: &Data, functions: &FunctionSet) {
+        self.training_outputs = Some(self.get_outputs(&mut 0, datas.train(), functions));
+        if datas.has_validation() {
+            self.validation_outputs = Some(self.get_outputs(&mut 0, datas.validation(), functions));
+        }
+    }
+
+    ///Calculate the fitness metric for the program outputs vs the data labels
+    /// With linear scaling the intercept and slope are fitted on the training outputs
+    /// and applied to the outputs of every partition
+    pub fn eval_fitness(&mut self, datas: &Data, metric: &dyn Metric) {
+        let training_outputs = self.training_outputs();
+        self.linear_scaling = None;
+        if metric.linear_scaling() {
+            self.linear_scaling = Some(linear_fit(&training_outputs, datas.train_targets()));
+        }
+        let outputs = self.scale_outputs(training_outputs);
+        self.train_fitness =
+            Some(metric.score(&outputs, datas.train_targets(), datas.train_weights()));
+        self.case_errors = Some(
+            outputs
+                .iter()
+                .zip(datas.train_targets())
+                .map(|(x, y)| (x - y).abs())
+                .collect(),
+        );
+        if let Some(outputs) = self.validation_outputs.clone() {
+            self.validation_fitness = Some(metric.score(
+                &self.scale_outputs(outputs),
+                datas.validation_targets(),
+                datas.validation_weights(),
+            ));
+        }
+    }
+
+    ///Apply the linear scaling, if any, to outputs of the tree
+    fn scale_outputs(&self, outputs: Vec<f32>) -> Vec<f32> {
+        match self.linear_scaling {
+            Some((intercept, slope)) => {
+                outputs.into_iter().map(|x| intercept + slope * x).collect()
             }
+            None => outputs,
         }
     }
 
-    ///Calculate the outputs for the training and test data
-    pub fn outputs_calculate(&mut self, datas: &Data) {
-        self.training_outputs = Some(self.get_outputs(0, datas.train()));
-        self.test_outputs = Some(self.get_outputs(0, datas.test()));
+    ///Intercept and slope applied to the outputs of the tree, None without linear scaling
+    pub fn linear_scaling(&self) -> Option<(f32, f32)> {
+        self.linear_scaling
     }
 
-    ///Calculate the root mean squared error for the program outputs vs the data labels
-    pub fn eval_fitness(&mut self, datas: &Data) {
-        self.train_fitness = Some(root_mean_squared(
-            &self.training_outputs(),
-            datas.train_targets(),
-        ));
-        self.test_fitness = Some(root_mean_squared(
-            &self.test_outputs(),
-            datas.test_targets(),
-        ));
+    ///The tree with its linear scaling, if any, written out as intercept + slope * tree
+    pub fn model(&self) -> Vec<Node> {
+        match self.linear_scaling {
+            Some((intercept, slope)) => {
+                let mut model = vec![
+                    Node::Addition,
+                    Node::Constant(intercept),
+                    Node::Multiplication,
+                    Node::Constant(slope),
+                ];
+                model.extend(self.chromosome.iter().cloned());
+                model
+            }
+            None => self.chromosome.clone(),
+        }
+    }
+
+    ///Calculate the outputs and fitness for the test data, meant to be called once on the final model.
+    /// The linear scaling fitted on the training data is applied to the test outputs
+    pub fn evaluate_test(&mut self, datas: &Data, functions: &FunctionSet, metric: &dyn Metric) {
+        let outputs = self.get_outputs(&mut 0, datas.test(), functions);
+        let outputs = self.scale_outputs(outputs);
+        self.test_fitness =
+            Some(metric.score(&outputs, datas.test_targets(), datas.test_weights()));
+        self.test_outputs = Some(outputs);
     }
 
     ///Determine number of nodes in a subtree starting at an index
@@ -329,48 +569,108 @@ impl Individual {
         }
     }
 
-    pub fn print_nodes(&self, file: &mut File) {
-        let mut first: String = match self.chromosome[0] {
-            Node::Addition => "+".to_string(),
-            Node::Subtraction => "-".to_string(),
-            Node::Sine => "sin(".to_string(),
-            Node::Multiplication => "*".to_string(),
-            Node::Division => "/".to_string(),
-            Node::Ln => "ln(".to_string(),
-            Node::Square => "square(".to_string(),
-            Node::Constant(x) => format!("({})", x),
-            Node::Input(j) => format!("x{}", j),
-            _ => "".to_string(),
-        };
-        first.push_str("(");
-        // println!("{}", first);
-
-        for i in 1..self.chromosome.len() {
-            let nodey: String = match self.chromosome[i] {
-                Node::Addition => "+".to_string(),
-                Node::Subtraction => "-".to_string(),
-                Node::Sine => "sin(".to_string(),
-                Node::Multiplication => "*".to_string(),
-                Node::Division => "/".to_string(),
-                Node::Ln => "ln(".to_string(),
-                Node::Square => "square(".to_string(),
-                Node::Constant(x) => format!("({})", x),
-                Node::Input(j) => format!("x{}", j),
-                _ => "".to_string(),
-            };
-            if self.chromosome[i].arity() == 2 {
+    ///Build the prefix expression of the tree, printing inputs with the given variable names,
+    /// including the linear scaling if there is one
+    pub fn expression(&self, names: &[String]) -> String {
+        let model = self.model();
+        let mut first: String = model[0].symbol(names);
+        first.push('(');
+
+        for node in model.iter().skip(1) {
+            let nodey: String = node.symbol(names);
+            if node.arity() >= 2 {
                 let tmp: String = format!("{}(", nodey);
                 first.push_str(&tmp);
-                // print!("{}(", nodey);
-            } else if self.chromosome[i].arity() == 1 {
+            } else if node.arity() == 1 {
                 first.push_str(&nodey);
-                // print!("{}", nodey);
             } else {
                 let tmp: String = format!("{})", nodey);
                 first.push_str(&tmp);
-                // print!("{})", nodey);
             }
         }
+        first
+    }
+
+    ///Rewrite the tree from the scaled space of the data back into original units.
+    /// Every input becomes (x - offset) / scale and the output is mapped back with
+    /// output * scale + offset of the target column.  The linear scaling, if any, becomes part of the tree
+    pub fn to_original_units(&self, datas: &Data) -> Individual {
+        let scales = datas.scales();
+        let target = scales[datas.dimensions()];
+        let mut x = Individual::new();
+        if !target.is_identity() {
+            x.chromosome.push(Node::Addition);
+            x.chromosome.push(Node::Multiplication);
+        }
+        for node in &self.model() {
+            match *node {
+                Node::Input(j) if !scales[j].is_identity() => {
+                    x.chromosome.push(Node::Division);
+                    x.chromosome.push(Node::Subtraction);
+                    x.chromosome.push(Node::Input(j));
+                    x.chromosome.push(Node::Constant(scales[j].offset));
+                    x.chromosome.push(Node::Constant(scales[j].scale));
+                }
+                _ => x.chromosome.push(node.clone()),
+            }
+        }
+        if !target.is_identity() {
+            x.chromosome.push(Node::Constant(target.scale));
+            x.chromosome.push(Node::Constant(target.offset));
+        }
+        x.size = Some(x.chromosome.len());
+        x.depth_calc();
+        x
+    }
+
+    ///Algebraically simplified copy of the tree, evaluated on the training and validation data.
+    /// Every rewrite is checked to give the same outputs on the training data, see simplify::simplify()
+    pub fn simplify(
+        &self,
+        datas: &Data,
+        functions: &FunctionSet,
+        metric: &dyn Metric,
+    ) -> Individual {
+        let mut x = Individual::new();
+        x.chromosome = simplify::simplify(&self.chromosome, datas, functions);
+        x.outputs_calculate(datas, functions);
+        x.eval_fitness(datas, metric);
+        x.size = Some(x.chromosome.len());
+        x.depth_calc();
+        x
+    }
+
+    ///Copy of the individual with its constants tuned by Levenberg-Marquardt for at most the
+    /// given number of iterations, see optimize::levenberg_marquardt().  The copy is evaluated
+    /// on the training and validation data and is only returned if its training fitness is better.
+    /// Also returns the number of evaluations on the training data the tuning took
+    pub fn optimize_constants(
+        &self,
+        datas: &Data,
+        functions: &FunctionSet,
+        metric: &dyn Metric,
+        iterations: usize,
+    ) -> (Individual, usize) {
+        let mut x = self.clone();
+        let evaluations;
+        (x.chromosome, evaluations) = levenberg_marquardt(
+            &self.chromosome,
+            datas,
+            functions,
+            self.linear_scaling,
+            iterations,
+        );
+        x.outputs_calculate(datas, functions);
+        x.eval_fitness(datas, metric);
+        let tuned = match (x.train_fitness, self.train_fitness) {
+            (Some(new), Some(old)) if !metric.is_better(new, old) => self.clone(),
+            _ => x,
+        };
+        (tuned, evaluations + 1)
+    }
+
+    pub fn print_nodes(&self, file: &mut File, names: &[String]) {
+        let first = self.expression(names);
         println!("{}", &first);
         writeln!(file, "{}", first).expect("write failed");
     }
@@ -379,15 +679,23 @@ impl Individual {
 pub mod genetics {
     extern crate rand;
     use crate::base::data::Data;
+    use crate::base::fitness::Metric;
+    use crate::base::function_set::FunctionSet;
     use crate::base::individual::Individual;
     use crate::base::individual::Node;
-    use rand::{thread_rng, Rng};
+    use rand::Rng;
 
     ///Subtree crossover.  Random points chosen for parents 1 and 2
     /// the subtree of parent 1 is replaced by result subtree from parent 2.
-    pub fn subtree_cross(parent1: &Individual, parent2: &Individual, datas: &Data) -> Individual {
+    pub fn subtree_cross<R: Rng + ?Sized>(
+        parent1: &Individual,
+        parent2: &Individual,
+        datas: &Data,
+        functions: &FunctionSet,
+        metric: &dyn Metric,
+        rng: &mut R,
+    ) -> Individual {
         let mut child = Individual::new();
-        let mut rng = thread_rng();
         let cross_point1: usize = rng.gen_range(0..parent1.size());
         let cross_point2: usize = rng.gen_range(0..parent2.size());
         let sub_parent1: usize = parent1.nodes_subtree(cross_point1);
@@ -400,9 +708,13 @@ pub mod genetics {
         child.insert(parent2_subtree);
         child.insert(parent1_right);
 
+        //reject child tree if it may leave the domain of a primitive
+        if functions.domain_check() && child.output_range(datas, functions).is_none() {
+            return parent1.clone();
+        }
         //evaluate child tree
-        child.outputs_calculate(datas);
-        child.eval_fitness(datas);
+        child.outputs_calculate(datas, functions);
+        child.eval_fitness(datas, metric);
         if child.train_fitness.unwrap().is_nan() {
             return parent1.clone();
         }
@@ -418,14 +730,19 @@ pub mod genetics {
     ///Single point mutation.  A random subtree is generated using the
     /// grow() method to replace a subtree from parent1
     /// new subtree has max depth of 5
-    pub fn single_point_mut(parent1: &Individual, datas: &Data) -> Individual {
+    pub fn single_point_mut<R: Rng + ?Sized>(
+        parent1: &Individual,
+        datas: &Data,
+        functions: &FunctionSet,
+        metric: &dyn Metric,
+        rng: &mut R,
+    ) -> Individual {
         let mut child: Individual = Individual::new();
-        let mut rng = thread_rng();
         let mut_point: usize = rng.gen_range(0..parent1.size());
         let parent1_subpoint: usize = parent1.nodes_subtree(mut_point);
 
         let parent1_left: Vec<Node> = parent1.left_copy_outside(mut_point);
-        let mutate: Individual = Individual::grow(5, datas);
+        let mutate: Individual = Individual::grow(5, datas, functions, rng);
         let new_point = mut_point + parent1_subpoint;
         let parent1_right: Vec<Node> = parent1.right_copy_outside(new_point);
 
@@ -434,9 +751,13 @@ pub mod genetics {
         child.insert(mutate.cloned());
         child.insert(parent1_right);
 
+        //reject child if it may leave the domain of a primitive
+        if functions.domain_check() && child.output_range(datas, functions).is_none() {
+            return parent1.clone();
+        }
         //evaluate child
-        child.outputs_calculate(datas);
-        child.eval_fitness(datas);
+        child.outputs_calculate(datas, functions);
+        child.eval_fitness(datas, metric);
         if child.train_fitness.unwrap().is_nan() {
             return parent1.clone();
         }
@@ -0,0 +1,229 @@
+use std::f32::consts::{FRAC_PI_2, PI};
+use std::fmt;
+
+use crate::base::functions::DIVISION_GUARD;
+use crate::base::individual::Node;
+
+///A closed range of values, used to bound the outputs of a tree over the ranges of its inputs
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Interval {
+    pub lower: f32,
+    pub upper: f32,
+}
+
+impl fmt::Display for Interval {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "[{:?}, {:?}]", self.lower, self.upper)
+    }
+}
+
+impl Interval {
+    pub fn new(lower: f32, upper: f32) -> Interval {
+        Interval { lower, upper }
+    }
+
+    ///Interval holding a single value
+    pub fn point(x: f32) -> Interval {
+        Interval::new(x, x)
+    }
+
+    ///Every value, including the infinities
+    pub fn entire() -> Interval {
+        Interval::new(f32::NEG_INFINITY, f32::INFINITY)
+    }
+
+    pub fn contains(&self, x: f32) -> bool {
+        self.lower <= x && x <= self.upper
+    }
+
+    pub fn is_finite(&self) -> bool {
+        self.lower.is_finite() && self.upper.is_finite()
+    }
+
+    ///Smallest interval holding both intervals
+    pub fn hull(&self, other: Interval) -> Interval {
+        Interval::new(self.lower.min(other.lower), self.upper.max(other.upper))
+    }
+
+    ///Replace infinite bounds with the largest finite values
+    pub fn clamp(&self) -> Interval {
+        Interval::new(
+            self.lower.clamp(f32::MIN, f32::MAX),
+            self.upper.clamp(f32::MIN, f32::MAX),
+        )
+    }
+
+    ///Smallest interval holding all the values
+    fn of(values: &[f32]) -> Interval {
+        let lower = values.iter().copied().fold(f32::INFINITY, f32::min);
+        let upper = values.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+        Interval::new(lower, upper)
+    }
+
+    ///Image of a monotonically increasing function
+    fn increasing(&self, f: impl Fn(f32) -> f32) -> Interval {
+        Interval::new(f(self.lower), f(self.upper))
+    }
+
+    ///Range of the absolute values
+    pub fn abs(&self) -> Interval {
+        if self.contains(0.0) {
+            Interval::new(0.0, self.lower.abs().max(self.upper.abs()))
+        } else {
+            Interval::of(&[self.lower.abs(), self.upper.abs()])
+        }
+    }
+
+    fn mul(&self, other: Interval) -> Interval {
+        Interval::of(&[
+            self.lower * other.lower,
+            self.lower * other.upper,
+            self.upper * other.lower,
+            self.upper * other.upper,
+        ])
+    }
+
+    ///True if the interval holds a point x + k * period for some integer k
+    fn hits(&self, x: f32, period: f32) -> bool {
+        let k = ((self.lower - x) / period).ceil();
+        x + k * period <= self.upper
+    }
+
+    ///Image of sin or cos, given where the function peaks at 1 and drops to -1 within a period
+    fn periodic(&self, f: impl Fn(f32) -> f32, peak: f32, trough: f32) -> Interval {
+        //far from zero the spacing of f32 values is too coarse to locate the extrema
+        if self.upper - self.lower >= 2.0 * PI || self.lower.abs().max(self.upper.abs()) > 1e6 {
+            return Interval::new(-1.0, 1.0);
+        }
+        let ends = Interval::of(&[f(self.lower), f(self.upper)]);
+        let lower = if self.hits(trough, 2.0 * PI) {
+            -1.0
+        } else {
+            ends.lower
+        };
+        let upper = if self.hits(peak, 2.0 * PI) {
+            1.0
+        } else {
+            ends.upper
+        };
+        Interval::new(lower, upper)
+    }
+
+    ///Compare two intervals, Some(true) if every x is above every y,
+    /// Some(false) if no x is above any y, None if it depends on the values
+    fn above(&self, other: Interval) -> Option<bool> {
+        if self.lower > other.upper {
+            Some(true)
+        } else if self.upper <= other.lower {
+            Some(false)
+        } else {
+            None
+        }
+    }
+}
+
+///Interval of 0 and 1 for a comparison
+fn truth(value: Option<bool>) -> Interval {
+    match value {
+        Some(true) => Interval::point(1.0),
+        Some(false) => Interval::point(0.0),
+        None => Interval::new(0.0, 1.0),
+    }
+}
+
+///Range of the outputs of a primitive with the built-in semantics over the ranges of its
+/// arguments, ignoring arguments where it is undefined.  The flag is false if some argument
+/// in the ranges lies outside the domain of the primitive (ln of a negative value, division
+/// by an interval containing zero, tan across an asymptote).
+/// The arguments are assumed to be finite, user-defined primitives are never proven defined
+pub fn node_interval(node: &Node, args: &[Interval]) -> (Interval, bool) {
+    match *node {
+        Node::Addition => (
+            Interval::new(args[0].lower + args[1].lower, args[0].upper + args[1].upper),
+            true,
+        ),
+        Node::Subtraction => (
+            Interval::new(args[0].lower - args[1].upper, args[0].upper - args[1].lower),
+            true,
+        ),
+        Node::Multiplication => (args[0].mul(args[1]), true),
+        Node::Division => {
+            let (num, denom) = (args[0], args[1]);
+            if denom.upper < -DIVISION_GUARD || denom.lower > DIVISION_GUARD {
+                let reciprocal = Interval::of(&[1.0 / denom.lower, 1.0 / denom.upper]);
+                (num.mul(reciprocal), true)
+            } else {
+                //near zero the numerator is returned, elsewhere |num / denom| < |num| / guard
+                let bound = Interval::new(-1.0 / DIVISION_GUARD, 1.0 / DIVISION_GUARD);
+                (num.mul(bound).hull(num), false)
+            }
+        }
+        Node::Sine => (args[0].periodic(f32::sin, FRAC_PI_2, -FRAC_PI_2), true),
+        Node::Cosine => (args[0].periodic(f32::cos, 0.0, PI), true),
+        Node::Tangent => {
+            let x = args[0];
+            if x.upper - x.lower >= PI
+                || x.lower.abs().max(x.upper.abs()) > 1e6
+                || x.hits(FRAC_PI_2, PI)
+            {
+                (Interval::entire(), false)
+            } else {
+                (x.increasing(f32::tan), true)
+            }
+        }
+        Node::Ln => {
+            let x = args[0];
+            (
+                Interval::new(x.lower.max(0.0).ln(), x.upper.max(0.0).ln()),
+                x.lower >= 0.0,
+            )
+        }
+        Node::Square => {
+            let x = args[0].abs();
+            (Interval::new(x.lower * x.lower, x.upper * x.upper), true)
+        }
+        Node::Exp => (args[0].increasing(f32::exp), true),
+        Node::Sqrt => (args[0].abs().increasing(f32::sqrt), true),
+        Node::Abs => (args[0].abs(), true),
+        Node::Tanh => (args[0].increasing(f32::tanh), true),
+        Node::Min => (
+            Interval::new(
+                args[0].lower.min(args[1].lower),
+                args[0].upper.min(args[1].upper),
+            ),
+            true,
+        ),
+        Node::Max => (
+            Interval::new(
+                args[0].lower.max(args[1].lower),
+                args[0].upper.max(args[1].upper),
+            ),
+            true,
+        ),
+        Node::Power => {
+            //|x|^y is monotonic in each argument so the extremes are at the corners
+            let (x, y) = (args[0].abs(), args[1]);
+            (
+                Interval::of(&[
+                    x.lower.powf(y.lower),
+                    x.lower.powf(y.upper),
+                    x.upper.powf(y.lower),
+                    x.upper.powf(y.upper),
+                ]),
+                true,
+            )
+        }
+        Node::GreaterThan => (truth(args[0].above(args[1])), true),
+        Node::LessThan => (truth(args[1].above(args[0])), true),
+        Node::Step => (truth(args[0].above(Interval::point(0.0))), true),
+        Node::IfGreaterThanZero => match args[0].above(Interval::point(0.0)) {
+            Some(true) => (args[1], true),
+            Some(false) => (args[2], true),
+            None => (args[1].hull(args[2]), true),
+        },
+        Node::Custom(_) => (Interval::entire(), false),
+        Node::Input(_) | Node::Constant(_) => {
+            panic!("Non-functional node.  Cannot perform operation.")
+        }
+    }
+}
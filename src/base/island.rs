@@ -0,0 +1,95 @@
+use rand::Rng;
+
+use crate::base::pop::Population;
+use crate::base::selection::{Selection, Tournament};
+
+///A sub-population of an island model run, evolving independently with its own parameters
+pub struct Island {
+    pub(crate) population: Population,
+    ///None until evolve_islands() fills it in from the Gp
+    pub(crate) population_size: Option<usize>,
+    ///None until evolve_islands() fills it in from the Gp
+    pub(crate) cross_chance: Option<f32>,
+    pub(crate) selection: Box<dyn Selection>,
+}
+
+impl Island {
+    ///Island of the given population size with 80% crossover and a tournament of size 3
+    pub fn new(population_size: usize) -> Island {
+        Island {
+            population: Population::new(),
+            population_size: Some(population_size),
+            cross_chance: Some(0.8),
+            selection: Box::new(Tournament::new(3)),
+        }
+    }
+
+    ///Island with a tournament of size 3 taking its population size and crossover chance
+    /// from the Gp when evolve_islands() starts, whatever order the Gp was built in
+    pub(crate) fn inheriting() -> Island {
+        Island {
+            population: Population::new(),
+            population_size: None,
+            cross_chance: None,
+            selection: Box::new(Tournament::new(3)),
+        }
+    }
+
+    pub fn set_cross_chance(mut self, cross_chance: f32) -> Island {
+        self.cross_chance = Some(cross_chance);
+        self
+    }
+
+    ///Set the method used to choose parents on this island
+    pub fn set_selection<S: Selection + 'static>(mut self, selection: S) -> Island {
+        self.selection = Box::new(selection);
+        self
+    }
+
+    pub fn population(&self) -> &Population {
+        &self.population
+    }
+}
+
+///Which islands send their migrants to which
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Topology {
+    ///Island i sends to island i + 1, the last one to the first
+    Ring,
+    ///Every island sends to every other island
+    FullyConnected,
+    ///Every island sends to another island drawn at random at each migration
+    Random,
+}
+
+impl Topology {
+    ///Pairs of (source, destination) islands for one migration between n islands
+    pub fn routes<R: Rng + ?Sized>(&self, n: usize, rng: &mut R) -> Vec<(usize, usize)> {
+        if n < 2 {
+            return vec![];
+        }
+        match self {
+            Topology::Ring => (0..n).map(|i| (i, (i + 1) % n)).collect(),
+            Topology::FullyConnected => (0..n)
+                .flat_map(|i| (0..n).filter(move |&j| j != i).map(move |j| (i, j)))
+                .collect(),
+            Topology::Random => (0..n)
+                .map(|i| {
+                    //draw from the other n - 1 islands
+                    let j = rng.gen_range(0..n - 1);
+                    (i, if j >= i { j + 1 } else { j })
+                })
+                .collect(),
+        }
+    }
+}
+
+///When and how individuals move between islands
+#[derive(Debug, Clone, Copy)]
+pub struct Migration {
+    ///Generations between migrations
+    pub interval: usize,
+    ///Number of best individuals each island sends along each route
+    pub count: usize,
+    pub topology: Topology,
+}
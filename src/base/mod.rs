@@ -1,5 +1,13 @@
 pub mod data;
+pub mod fitness;
+pub mod function_set;
 pub mod functions;
 pub mod gp;
 pub mod individual;
+pub mod interval;
+pub mod island;
+pub mod optimize;
+pub mod pareto;
 pub mod pop;
+pub mod selection;
+pub mod simplify;
@@ -0,0 +1,395 @@
+use crate::base::data::Data;
+use crate::base::function_set::{FunctionSet, Protection};
+use crate::base::functions::DIVISION_GUARD;
+use crate::base::individual::Node;
+
+///Which models have their constants tuned during a run, see Gp::set_tuning()
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Tuning {
+    ///The given fraction of the population with the best fitness, after every generation
+    Top(f32),
+    ///Only the best model of the run, before it is tested
+    Final,
+}
+
+///Derivatives of the outputs with respect to constants, as (index of the constant,
+/// derivative for every sample)
+type Jacobian = Vec<(usize, Vec<f32>)>;
+
+///Tune the constants of a chromosome with Levenberg-Marquardt to minimize the squared error
+/// on the training data, weighted if the data has weights.  The outputs of the tree go
+/// through the linear scaling, if any.  Each of the iterations tries one step, steps that
+/// do not lower the error are rejected.  Returns the tuned chromosome, unchanged if there
+/// are no constants or the derivatives are not finite, with the number of times the tree
+/// was evaluated on the training data
+pub fn levenberg_marquardt(
+    chromosome: &[Node],
+    datas: &Data,
+    functions: &FunctionSet,
+    scaling: Option<(f32, f32)>,
+    iterations: usize,
+) -> (Vec<Node>, usize) {
+    let mut params = constants(chromosome);
+    if params.is_empty() {
+        return (chromosome.to_vec(), 0);
+    }
+    let (intercept, slope) = scaling.unwrap_or((0.0, 1.0));
+    let targets = datas.train_targets();
+    let weights = datas.train_weights();
+    let weight = |i: usize| weights.map_or(1.0, |w| w[i] as f64);
+
+    //weighted residuals and squared error of a chromosome
+    let residuals = |nodes: &[Node]| -> (Jacobian, Vec<f64>, f64) {
+        let (outputs, derivatives) = derivatives(nodes, &mut 0, &mut 0, datas.train(), functions);
+        let residuals: Vec<f64> = outputs
+            .iter()
+            .zip(targets)
+            .map(|(x, y)| (*y - (intercept + slope * x)) as f64)
+            .collect();
+        let error = residuals
+            .iter()
+            .enumerate()
+            .map(|(i, r)| weight(i) * r * r)
+            .sum();
+        (derivatives, residuals, error)
+    };
+
+    let mut best = chromosome.to_vec();
+    let (mut jacobian, mut residual, mut error) = residuals(&best);
+    let mut evaluations = 1;
+    let mut lambda = 1e-3;
+    let k = params.len();
+    for _ in 0..iterations {
+        if !error.is_finite() {
+            break;
+        }
+        //normal equations (J'WJ + lambda * diag(J'WJ)) step = J'Wr
+        let mut matrix = vec![vec![0.0f64; k]; k];
+        let mut gradient = vec![0.0f64; k];
+        for (a, da) in &jacobian {
+            for (i, d) in da.iter().enumerate() {
+                gradient[*a] += weight(i) * slope as f64 * *d as f64 * residual[i];
+            }
+            for (b, db) in &jacobian {
+                matrix[*a][*b] += da
+                    .iter()
+                    .zip(db)
+                    .enumerate()
+                    .map(|(i, (x, y))| weight(i) * (slope * slope * x * y) as f64)
+                    .sum::<f64>();
+            }
+        }
+        if matrix
+            .iter()
+            .flatten()
+            .chain(&gradient)
+            .any(|x| !x.is_finite())
+        {
+            break;
+        }
+        for (i, row) in matrix.iter_mut().enumerate() {
+            row[i] += lambda * row[i].max(1e-9);
+        }
+        let step = match solve(matrix, gradient) {
+            Some(step) => step,
+            None => break,
+        };
+        let trial: Vec<f32> = params
+            .iter()
+            .zip(&step)
+            .map(|(p, s)| (*p as f64 + s) as f32)
+            .collect();
+        let nodes = with_constants(&best, &trial);
+        let (trial_jacobian, trial_residual, trial_error) = residuals(&nodes);
+        evaluations += 1;
+        if trial_error.is_finite() && trial_error < error {
+            let converged = error - trial_error <= 1e-10 * error;
+            (best, params) = (nodes, trial);
+            (jacobian, residual, error) = (trial_jacobian, trial_residual, trial_error);
+            lambda = (lambda / 10.0).max(1e-12);
+            if converged {
+                break;
+            }
+        } else {
+            lambda *= 10.0;
+            if lambda > 1e12 {
+                break;
+            }
+        }
+    }
+    (best, evaluations)
+}
+
+///Values of the constants in the order they appear in the chromosome
+fn constants(chromosome: &[Node]) -> Vec<f32> {
+    chromosome
+        .iter()
+        .filter_map(|n| match n {
+            Node::Constant(x) => Some(*x),
+            _ => None,
+        })
+        .collect()
+}
+
+///Copy of the chromosome with its constants replaced in order
+fn with_constants(chromosome: &[Node], values: &[f32]) -> Vec<Node> {
+    let mut values = values.iter();
+    chromosome
+        .iter()
+        .map(|n| match n {
+            Node::Constant(_) => Node::Constant(*values.next().unwrap()),
+            _ => n.clone(),
+        })
+        .collect()
+}
+
+///Forward-mode derivatives of the subtree starting at index with respect to its constants,
+/// numbered from constant onwards.  Both counters are advanced past the subtree
+fn derivatives(
+    chromosome: &[Node],
+    index: &mut usize,
+    constant: &mut usize,
+    data: &[Vec<f32>],
+    functions: &FunctionSet,
+) -> (Vec<f32>, Jacobian) {
+    let node = &chromosome[*index];
+    *index += 1;
+    match *node {
+        Node::Constant(x) => {
+            *constant += 1;
+            let n = data[0].len();
+            (vec![x; n], vec![(*constant - 1, vec![1.0; n])])
+        }
+        Node::Input(x) => (data[x].to_vec(), vec![]),
+        _ => {
+            let mut args = vec![];
+            let mut inner = vec![];
+            for _ in 0..node.arity() {
+                let (values, derivatives) =
+                    derivatives(chromosome, index, constant, data, functions);
+                args.push(values);
+                inner.push(derivatives);
+            }
+            let partials = partials(node, &args, functions);
+            //every constant lies under exactly one argument, so the chain rule needs no sums
+            let outer = inner
+                .into_iter()
+                .zip(&partials)
+                .flat_map(|(derivatives, partial)| {
+                    derivatives
+                        .into_iter()
+                        .map(move |(c, d)| (c, d.iter().zip(partial).map(|(d, p)| d * p).collect()))
+                })
+                .collect();
+            (functions.apply(node, args), outer)
+        }
+    }
+}
+
+///Partial derivatives of a primitive with respect to each argument, for every sample.
+/// Built-ins under Protection::Default are differentiated exactly, other protection policies
+/// and user-defined primitives by central differences
+fn partials(node: &Node, args: &[Vec<f32>], functions: &FunctionSet) -> Vec<Vec<f32>> {
+    if functions.protection(node) != Protection::Default || matches!(node, Node::Custom(_)) {
+        return (0..args.len())
+            .map(|j| central_difference(node, args, j, functions))
+            .collect();
+    }
+    let unary = |f: fn(f32) -> f32| vec![args[0].iter().map(|&a| f(a)).collect()];
+    let binary = |f: fn(f32, f32) -> (f32, f32)| {
+        let (da, db) = args[0].iter().zip(&args[1]).map(|(&a, &b)| f(a, b)).unzip();
+        vec![da, db]
+    };
+    let sign = |a: f32| if a < 0.0 { -1.0 } else { 1.0 };
+    let zeros = vec![0.0; args[0].len()];
+    match *node {
+        Node::Addition => binary(|_, _| (1.0, 1.0)),
+        Node::Subtraction => binary(|_, _| (1.0, -1.0)),
+        Node::Multiplication => binary(|a, b| (b, a)),
+        Node::Division => binary(|a, b| {
+            if b.abs() > DIVISION_GUARD {
+                (1.0 / b, -a / (b * b))
+            } else {
+                (1.0, 0.0)
+            }
+        }),
+        Node::Sine => unary(f32::cos),
+        Node::Cosine => unary(|a| -a.sin()),
+        Node::Tangent => unary(|a| 1.0 + a.tan().powi(2)),
+        Node::Ln => unary(|a| 1.0 / a),
+        Node::Square => unary(|a| 2.0 * a),
+        Node::Exp => unary(f32::exp),
+        Node::Sqrt => vec![args[0]
+            .iter()
+            .map(|&a| sign(a) / (2.0 * a.abs().sqrt()))
+            .collect()],
+        Node::Abs => vec![args[0].iter().map(|&a| sign(a)).collect()],
+        Node::Tanh => unary(|a| 1.0 - a.tanh().powi(2)),
+        Node::Min => binary(|a, b| if a <= b { (1.0, 0.0) } else { (0.0, 1.0) }),
+        Node::Max => binary(|a, b| if a >= b { (1.0, 0.0) } else { (0.0, 1.0) }),
+        Node::Power => {
+            let (da, db) = args[0]
+                .iter()
+                .zip(&args[1])
+                .map(|(&a, &b)| {
+                    let base = a.abs();
+                    let db = if base > 0.0 {
+                        base.powf(b) * base.ln()
+                    } else {
+                        0.0
+                    };
+                    (sign(a) * b * base.powf(b - 1.0), db)
+                })
+                .unzip();
+            vec![da, db]
+        }
+        Node::GreaterThan | Node::LessThan => vec![zeros.clone(), zeros],
+        Node::Step => vec![zeros],
+        Node::IfGreaterThanZero => {
+            let then = args[0].iter().map(|&c| if c > 0.0 { 1.0 } else { 0.0 });
+            vec![
+                zeros,
+                then.clone().collect(),
+                then.map(|t| 1.0 - t).collect(),
+            ]
+        }
+        Node::Custom(_) | Node::Input(_) | Node::Constant(_) => {
+            panic!("Non-functional node.  Cannot perform operation.")
+        }
+    }
+}
+
+///Derivative with respect to argument j by central differences through FunctionSet::apply,
+/// so the protection policy is taken into account
+fn central_difference(
+    node: &Node,
+    args: &[Vec<f32>],
+    j: usize,
+    functions: &FunctionSet,
+) -> Vec<f32> {
+    let steps: Vec<f32> = args[j].iter().map(|a| 1e-3 * a.abs().max(1.0)).collect();
+    let shifted = |direction: f32| {
+        let mut shifted = args.to_vec();
+        for (a, h) in shifted[j].iter_mut().zip(&steps) {
+            *a += direction * h;
+        }
+        functions.apply(node, shifted)
+    };
+    let (up, down) = (shifted(1.0), shifted(-1.0));
+    up.iter()
+        .zip(&down)
+        .zip(&steps)
+        .map(|((u, d), h)| (u - d) / (2.0 * h))
+        .collect()
+}
+
+///Solve a linear system by Gaussian elimination with partial pivoting, None if singular
+fn solve(mut matrix: Vec<Vec<f64>>, mut vector: Vec<f64>) -> Option<Vec<f64>> {
+    let n = vector.len();
+    for col in 0..n {
+        let pivot =
+            (col..n).max_by(|&a, &b| matrix[a][col].abs().total_cmp(&matrix[b][col].abs()))?;
+        if matrix[pivot][col].abs() < 1e-300 {
+            return None;
+        }
+        matrix.swap(col, pivot);
+        vector.swap(col, pivot);
+        for row in col + 1..n {
+            let factor = matrix[row][col] / matrix[col][col];
+            let (above, below) = matrix.split_at_mut(row);
+            for (x, p) in below[0][col..].iter_mut().zip(&above[col][col..]) {
+                *x -= factor * p;
+            }
+            vector[row] -= factor * vector[col];
+        }
+    }
+    let mut x = vec![0.0; n];
+    for row in (0..n).rev() {
+        let sum: f64 = (row + 1..n).map(|k| matrix[row][k] * x[k]).sum();
+        x[row] = (vector[row] - sum) / matrix[row][row];
+    }
+    Some(x)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use Node::*;
+
+    #[test]
+    fn recovers_line_constants() {
+        let x: Vec<f32> = (0..20).map(|i| i as f32 / 4.0).collect();
+        let y: Vec<f32> = x.iter().map(|x| 2.0 + 3.0 * x).collect();
+        let datas = Data::from_series(&["x0", "y"], &[x, y], 0.2).unwrap();
+        let line = vec![
+            Addition,
+            Constant(1.0),
+            Multiplication,
+            Constant(1.0),
+            Input(0),
+        ];
+        let (tuned, evaluations) =
+            levenberg_marquardt(&line, &datas, &FunctionSet::new(), None, 50);
+        let fitted = constants(&tuned);
+        assert!((fitted[0] - 2.0).abs() < 1e-3, "intercept {}", fitted[0]);
+        assert!((fitted[1] - 3.0).abs() < 1e-3, "slope {}", fitted[1]);
+        assert!(evaluations > 1 && evaluations <= 51);
+    }
+
+    #[test]
+    fn solves_known_system() {
+        let x = solve(vec![vec![2.0, 1.0], vec![1.0, 3.0]], vec![3.0, 5.0]).unwrap();
+        assert!((x[0] - 0.8).abs() < 1e-12 && (x[1] - 1.4).abs() < 1e-12);
+        assert!(solve(vec![vec![1.0, 2.0], vec![2.0, 4.0]], vec![1.0, 2.0]).is_none());
+    }
+
+    #[test]
+    fn partials_match_central_differences() {
+        //away from kinks, ties, asymptotes and the division guard
+        let args = [
+            vec![0.3, 0.7, 1.1],
+            vec![0.5, 1.3, 0.9],
+            vec![-0.4, 0.8, 1.5],
+        ];
+        let functions = FunctionSet::new();
+        let nodes = [
+            Addition,
+            Subtraction,
+            Multiplication,
+            Division,
+            Sine,
+            Cosine,
+            Tangent,
+            Ln,
+            Square,
+            Exp,
+            Sqrt,
+            Abs,
+            Tanh,
+            Min,
+            Max,
+            Power,
+            GreaterThan,
+            LessThan,
+            Step,
+            IfGreaterThanZero,
+        ];
+        for node in nodes {
+            let args = &args[..node.arity()];
+            let exact = partials(&node, args, &functions);
+            for (j, exact) in exact.iter().enumerate() {
+                let numeric = central_difference(&node, args, j, &functions);
+                for (e, n) in exact.iter().zip(&numeric) {
+                    assert!(
+                        (e - n).abs() <= 1e-2 * e.abs().max(1.0),
+                        "{:?} argument {}: {} against {}",
+                        node,
+                        j,
+                        e,
+                        n
+                    );
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,225 @@
+use std::cmp::Ordering;
+
+use rand::{Rng, RngCore};
+
+use crate::base::fitness::{Direction, Metric};
+use crate::base::individual::Individual;
+use crate::base::pop::Population;
+use crate::base::selection::Selection;
+
+///Measure of how complex a model is, the second objective of multi-objective evolution
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Complexity {
+    ///Number of nodes in the tree
+    Size,
+    ///Depth of the tree
+    Depth,
+}
+
+impl Complexity {
+    pub fn measure(&self, ind: &Individual) -> f32 {
+        match self {
+            Complexity::Size => ind.size() as f32,
+            Complexity::Depth => ind.depth() as f32,
+        }
+    }
+}
+
+///Objectives of an individual as (error, complexity), both minimized.
+/// Maximized fitness is negated and NaN fitness becomes infinite
+pub fn objectives(ind: &Individual, metric: &dyn Metric, complexity: Complexity) -> (f32, f32) {
+    let fit = ind.train_fit().expect("no training error computed");
+    let error = match metric.direction() {
+        _ if fit.is_nan() => f32::INFINITY,
+        Direction::Minimize => fit,
+        Direction::Maximize => -fit,
+    };
+    (error, complexity.measure(ind))
+}
+
+///True if a is no worse than b in both objectives and better in at least one
+fn dominates(a: (f32, f32), b: (f32, f32)) -> bool {
+    a.0 <= b.0 && a.1 <= b.1 && (a.0 < b.0 || a.1 < b.1)
+}
+
+///Fast non-dominated sort, returns the fronts as indices, the Pareto front first
+pub fn non_dominated_sort(objectives: &[(f32, f32)]) -> Vec<Vec<usize>> {
+    let n = objectives.len();
+    let mut dominated: Vec<Vec<usize>> = vec![vec![]; n];
+    let mut counts: Vec<usize> = vec![0; n];
+    let mut fronts: Vec<Vec<usize>> = vec![vec![]];
+    for i in 0..n {
+        for j in 0..n {
+            if dominates(objectives[i], objectives[j]) {
+                dominated[i].push(j);
+            } else if dominates(objectives[j], objectives[i]) {
+                counts[i] += 1;
+            }
+        }
+        if counts[i] == 0 {
+            fronts[0].push(i);
+        }
+    }
+    let mut current = 0;
+    while !fronts[current].is_empty() {
+        let mut next = vec![];
+        for &i in &fronts[current] {
+            for &j in &dominated[i] {
+                counts[j] -= 1;
+                if counts[j] == 0 {
+                    next.push(j);
+                }
+            }
+        }
+        fronts.push(next);
+        current += 1;
+    }
+    fronts.pop();
+    fronts
+}
+
+///Crowding distance of each member of a front, in the order of the front.
+/// The extremes of each objective get an infinite distance
+pub fn crowding_distance(objectives: &[(f32, f32)], front: &[usize]) -> Vec<f32> {
+    let mut distance = vec![0.0; front.len()];
+    let objective = |i: usize, k: usize| match k {
+        0 => objectives[front[i]].0,
+        _ => objectives[front[i]].1,
+    };
+    for k in 0..2 {
+        let mut order: Vec<usize> = (0..front.len()).collect();
+        order.sort_by(|&a, &b| objective(a, k).total_cmp(&objective(b, k)));
+        let (first, last) = (order[0], order[order.len() - 1]);
+        distance[first] = f32::INFINITY;
+        distance[last] = f32::INFINITY;
+        let span = objective(last, k) - objective(first, k);
+        if span == 0.0 || !span.is_finite() {
+            continue;
+        }
+        for w in order.windows(3) {
+            distance[w[1]] += (objective(w[2], k) - objective(w[0], k)) / span;
+        }
+    }
+    distance
+}
+
+///Rank (front number) and crowding distance of every individual
+fn rank_and_crowding(objectives: &[(f32, f32)]) -> (Vec<usize>, Vec<f32>) {
+    let mut rank = vec![0; objectives.len()];
+    let mut crowding = vec![0.0; objectives.len()];
+    for (r, front) in non_dominated_sort(objectives).iter().enumerate() {
+        for (&i, d) in front.iter().zip(crowding_distance(objectives, front)) {
+            rank[i] = r;
+            crowding[i] = d;
+        }
+    }
+    (rank, crowding)
+}
+
+///Indices of the n individuals NSGA-II keeps: whole fronts in order, then the least
+/// crowded members of the front that does not fit
+pub fn survivors(objectives: &[(f32, f32)], n: usize) -> Vec<usize> {
+    let mut kept = vec![];
+    for front in non_dominated_sort(objectives) {
+        if kept.len() + front.len() <= n {
+            kept.extend(front);
+        } else {
+            let distance = crowding_distance(objectives, &front);
+            let mut order: Vec<usize> = (0..front.len()).collect();
+            order.sort_by(|&a, &b| distance[b].total_cmp(&distance[a]));
+            kept.extend(order.into_iter().take(n - kept.len()).map(|i| front[i]));
+        }
+        if kept.len() == n {
+            break;
+        }
+    }
+    kept
+}
+
+///Binary tournament of NSGA-II, the lower front wins and the less crowded breaks ties
+#[derive(Debug, Clone)]
+pub struct CrowdedTournament {
+    complexity: Complexity,
+    rank: Vec<usize>,
+    crowding: Vec<f32>,
+}
+
+impl CrowdedTournament {
+    pub fn new(complexity: Complexity) -> CrowdedTournament {
+        CrowdedTournament {
+            complexity,
+            rank: vec![],
+            crowding: vec![],
+        }
+    }
+}
+
+impl Selection for CrowdedTournament {
+    fn prepare(&mut self, pop: &Population, metric: &dyn Metric, _rng: &mut dyn RngCore) {
+        let objectives: Vec<(f32, f32)> = pop
+            .individuals()
+            .iter()
+            .map(|i| objectives(i, metric, self.complexity))
+            .collect();
+        (self.rank, self.crowding) = rank_and_crowding(&objectives);
+    }
+
+    fn select(&self, pop: &Population, _metric: &dyn Metric, rng: &mut dyn RngCore) -> Individual {
+        let n = pop.size();
+        let (a, b) = (rng.gen_range(0..n), rng.gen_range(0..n));
+        let winner = match self.rank[a].cmp(&self.rank[b]) {
+            Ordering::Less => a,
+            Ordering::Greater => b,
+            Ordering::Equal => {
+                if self.crowding[b] > self.crowding[a] {
+                    b
+                } else {
+                    a
+                }
+            }
+        };
+        pop.individuals()[winner].clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //two points trade off with the third, one is dominated once and one twice
+    const OBJECTIVES: [(f32, f32); 5] =
+        [(1.0, 3.0), (2.0, 2.0), (3.0, 1.0), (2.0, 3.0), (3.0, 3.0)];
+
+    #[test]
+    fn fronts_in_order() {
+        let fronts = non_dominated_sort(&OBJECTIVES);
+        assert_eq!(fronts, vec![vec![0, 1, 2], vec![3], vec![4]]);
+    }
+
+    #[test]
+    fn crowding_infinite_at_extremes() {
+        let distance = crowding_distance(&OBJECTIVES, &[0, 1, 2]);
+        assert_eq!(distance[0], f32::INFINITY);
+        assert_eq!(distance[2], f32::INFINITY);
+        assert_eq!(distance[1], 2.0);
+    }
+
+    #[test]
+    fn duplicate_objectives_share_a_front() {
+        let objectives = [(1.0, 1.0), (1.0, 1.0), (2.0, 2.0)];
+        let fronts = non_dominated_sort(&objectives);
+        assert_eq!(fronts, vec![vec![0, 1], vec![2]]);
+        let distance = crowding_distance(&objectives, &fronts[0]);
+        assert!(distance.iter().all(|d| !d.is_nan()));
+    }
+
+    #[test]
+    fn survivors_truncate_last_front_by_crowding() {
+        let mut kept = survivors(&OBJECTIVES, 2);
+        kept.sort();
+        assert_eq!(kept, vec![0, 2]);
+        let mut kept = survivors(&OBJECTIVES, 4);
+        kept.sort();
+        assert_eq!(kept, vec![0, 1, 2, 3]);
+    }
+}
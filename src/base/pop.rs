@@ -1,7 +1,8 @@
-use rand::prelude::SliceRandom;
-use rand::{thread_rng, Rng};
+use rand::Rng;
 
 use crate::base::data::Data;
+use crate::base::fitness::Metric;
+use crate::base::function_set::FunctionSet;
 use crate::base::individual::Individual;
 
 #[derive(Debug)]
@@ -9,6 +10,12 @@ pub struct Population {
     pop: Vec<Individual>,
 }
 
+impl Default for Population {
+    fn default() -> Self {
+        Population::new()
+    }
+}
+
 impl Population {
     ///Size of population
     pub fn size(&self) -> usize {
@@ -19,6 +26,11 @@ impl Population {
         self.pop.clone()
     }
 
+    ///Borrow the individuals of the population
+    pub fn individuals(&self) -> &[Individual] {
+        &self.pop
+    }
+
     ///Init empty population
     pub fn new() -> Population {
         Population { pop: vec![] }
@@ -44,16 +56,42 @@ impl Population {
         self.pop.extend(indivs)
     }
 
-    ///Population sort by fitness
-    pub fn fit_sort(&mut self) {
-        //this needs Rust nightly compiler build to work so am using stopgap method for now
-        // self.pop
-        //     .sort_by(|a, b| a.train_fit().unwrap().total_cmp(&b.train_fit().unwrap()));
-        self.pop.sort_by_key(|ind| {
-            (ind.train_fit().expect("no training error computed") * 1000000.0) as u64
+    ///Population sort by fitness, best first according to the metric's direction
+    pub fn fit_sort(&mut self, metric: &dyn Metric) {
+        self.pop.sort_by(|a, b| {
+            metric.compare(
+                a.train_fit().expect("no training error computed"),
+                b.train_fit().expect("no training error computed"),
+            )
         });
     }
 
+    ///Index of the least fit of size randomly drawn individuals, for steady-state replacement.
+    /// The population must be sorted, the first protect individuals are never drawn
+    pub fn inverse_tournament<R: Rng + ?Sized>(
+        &self,
+        size: usize,
+        protect: usize,
+        rng: &mut R,
+    ) -> usize {
+        let from = protect.min(self.pop.len() - 1);
+        (0..size)
+            .map(|_| rng.gen_range(from..self.pop.len()))
+            .max()
+            .expect("Tournament size must be positive")
+    }
+
+    ///Replace the individual at index, keeping the population sorted by fitness
+    pub fn replace(&mut self, index: usize, indiv: Individual, metric: &dyn Metric) {
+        self.pop.remove(index);
+        let fit = indiv.train_fit().expect("no training error computed");
+        let position = self.pop.partition_point(|x| {
+            metric.compare(x.train_fit().expect("no training error computed"), fit)
+                != std::cmp::Ordering::Greater
+        });
+        self.pop.insert(position, indiv);
+    }
+
     ///To return best individual
     pub fn first_getter(&self) -> &Individual {
         &self.pop[0]
@@ -61,11 +99,18 @@ impl Population {
 
     ///Return the best "numb" of values from population as sorted by fitness
     pub fn get_best(&self, numb: usize) -> Vec<Individual> {
-        self.pop.iter().take(numb).map(|x| x.clone()).collect()
+        self.pop.iter().take(numb).cloned().collect()
     }
 
     ///Init population using ramped half and half strategy
-    pub fn ramped(psize: usize, max_depth: usize, datas: &Data) -> Population {
+    pub fn ramped<R: Rng + ?Sized>(
+        psize: usize,
+        max_depth: usize,
+        datas: &Data,
+        functions: &FunctionSet,
+        metric: &dyn Metric,
+        rng: &mut R,
+    ) -> Population {
         let mut pop = Population::new();
         let ind_layer: f32 = (psize / max_depth) as f32;
         let ind_remain: f32 = (psize % max_depth) as f32;
@@ -78,49 +123,20 @@ impl Population {
                 numb_pop_full = (ind_layer + ind_remain / 2.0).ceil() as i32;
             }
             for _ in 0..numb_pop_full {
-                let mut x = Individual::full(i, datas);
-                x.outputs_calculate(datas);
-                x.eval_fitness(datas);
+                let mut x = Individual::full(i, datas, functions, rng);
+                x.outputs_calculate(datas, functions);
+                x.eval_fitness(datas, metric);
                 x.depth_calc();
                 pop.pop.push(x);
             }
             for _ in 0..numb_pop_grow {
-                let mut x = Individual::grow(i, datas);
-                x.outputs_calculate(datas);
-                x.eval_fitness(datas);
+                let mut x = Individual::grow(i, datas, functions, rng);
+                x.outputs_calculate(datas, functions);
+                x.eval_fitness(datas, metric);
                 x.depth_calc();
                 pop.pop.push(x);
             }
         }
         pop
     }
-
-    ///Tournament Selection
-    /// Args:
-    ///  size: tournament size
-    pub fn tournament(&self, size: usize) -> Individual {
-        let mut rng = thread_rng();
-        let mut tourn: Vec<Individual> = vec![];
-        while tourn.len() < size {
-            let indiv = self.pop.choose(&mut rng).expect("empty");
-            tourn.push(indiv.clone());
-        }
-        //This needs Rust nightly so stopgap method used for now
-        // tourn.sort_by(|a, b| a.train_fit().unwrap().total_cmp(&b.train_fit().unwrap()));
-        tourn.sort_by_key(|ind| {
-            (ind.train_fit().expect("no training error computed") * 1000000.0) as u64
-        });
-        let child: Individual;
-        for i in &tourn {
-            if !i.train_fit().unwrap().is_nan() {
-                child = i.clone();
-                return child;
-            }
-        }
-        // let child = tourn[0].clone();
-        child = tourn[0].clone();
-        child
-    }
-
-    // pub fn tournament(&self, size: usize) -> &Individual {}
 }
@@ -0,0 +1,405 @@
+use std::cmp::Ordering;
+
+use rand::prelude::SliceRandom;
+use rand::{Rng, RngCore};
+
+use crate::base::fitness::{Direction, Metric};
+use crate::base::functions::median;
+use crate::base::individual::Individual;
+use crate::base::pop::Population;
+
+///A method of choosing parents from the population.
+/// select() is called from the threads breeding offspring in parallel
+pub trait Selection: Send + Sync {
+    ///Called before parents are chosen, to precompute what the method needs from the
+    /// population: once every generation, or before every offspring with steady-state replacement
+    fn prepare(&mut self, _pop: &Population, _metric: &dyn Metric, _rng: &mut dyn RngCore) {}
+
+    ///Choose one parent
+    fn select(&self, pop: &Population, metric: &dyn Metric, rng: &mut dyn RngCore) -> Individual;
+}
+
+///Training fitness of an individual
+fn fitness(ind: &Individual) -> f32 {
+    ind.train_fit().expect("no training error computed")
+}
+
+///Indices of the population from best to worst, NaN last
+fn ranking(pop: &Population, metric: &dyn Metric) -> Vec<usize> {
+    let individuals = pop.individuals();
+    let mut order: Vec<usize> = (0..individuals.len()).collect();
+    order.sort_by(|&a, &b| metric.compare(fitness(&individuals[a]), fitness(&individuals[b])));
+    order
+}
+
+///Draw an index with probability proportional to its weight, uniformly if all weights are 0
+fn spin(weights: &[f32], rng: &mut dyn RngCore) -> usize {
+    let total: f32 = weights.iter().sum();
+    if total <= 0.0 || !total.is_finite() {
+        return rng.gen_range(0..weights.len());
+    }
+    let mut pick = rng.gen_range(0.0..total);
+    for (i, weight) in weights.iter().enumerate() {
+        if pick < *weight {
+            return i;
+        }
+        pick -= weight;
+    }
+    //only reachable through rounding, fall back to the last index with a weight
+    weights.iter().rposition(|w| *w > 0.0).unwrap_or(0)
+}
+
+///Tournament selection, the fittest of size randomly drawn individuals wins
+#[derive(Debug, Clone, Copy)]
+pub struct Tournament {
+    size: usize,
+    replacement: bool,
+}
+
+impl Tournament {
+    ///Tournament drawing its competitors with replacement
+    pub fn new(size: usize) -> Tournament {
+        assert!(size > 0, "Tournament size must be positive");
+        Tournament {
+            size,
+            replacement: true,
+        }
+    }
+
+    ///Tournament drawing distinct competitors
+    pub fn without_replacement(size: usize) -> Tournament {
+        Tournament {
+            replacement: false,
+            ..Tournament::new(size)
+        }
+    }
+
+    ///Indices of the competitors of one tournament
+    fn competitors(&self, n: usize, rng: &mut dyn RngCore) -> Vec<usize> {
+        if self.replacement {
+            (0..self.size).map(|_| rng.gen_range(0..n)).collect()
+        } else {
+            rand::seq::index::sample(rng, n, self.size.min(n)).into_vec()
+        }
+    }
+
+    ///Index of the winner of one tournament
+    fn winner(&self, pop: &Population, metric: &dyn Metric, rng: &mut dyn RngCore) -> usize {
+        let individuals = pop.individuals();
+        assert!(!individuals.is_empty(), "empty");
+        self.competitors(individuals.len(), rng)
+            .into_iter()
+            .min_by(|&a, &b| metric.compare(fitness(&individuals[a]), fitness(&individuals[b])))
+            .expect("empty")
+    }
+}
+
+impl Selection for Tournament {
+    fn select(&self, pop: &Population, metric: &dyn Metric, rng: &mut dyn RngCore) -> Individual {
+        pop.individuals()[self.winner(pop, metric, rng)].clone()
+    }
+}
+
+///How fitness-proportional selection turns an error to be minimized into a selection weight
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Transform {
+    ///1 / (1 + error)
+    Inverse,
+    ///Worst error in the population minus the error
+    Subtract,
+}
+
+///Fitness-proportional (roulette wheel) selection.  Errors are transformed into weights,
+/// scores that are maximized are shifted so the worst has weight 0
+#[derive(Debug, Clone)]
+pub struct Roulette {
+    transform: Transform,
+    weights: Vec<f32>,
+}
+
+impl Roulette {
+    pub fn new(transform: Transform) -> Roulette {
+        Roulette {
+            transform,
+            weights: vec![],
+        }
+    }
+}
+
+impl Selection for Roulette {
+    fn prepare(&mut self, pop: &Population, metric: &dyn Metric, _rng: &mut dyn RngCore) {
+        let fits: Vec<f32> = pop.individuals().iter().map(fitness).collect();
+        let finite = fits.iter().copied().filter(|f| f.is_finite());
+        let worst = match metric.direction() {
+            Direction::Minimize => finite.fold(f32::NEG_INFINITY, f32::max),
+            Direction::Maximize => finite.fold(f32::INFINITY, f32::min),
+        };
+        self.weights = fits
+            .iter()
+            .map(|&f| {
+                let weight = match (metric.direction(), self.transform) {
+                    (Direction::Maximize, _) => f - worst,
+                    (Direction::Minimize, Transform::Inverse) => 1.0 / (1.0 + f.max(0.0)),
+                    (Direction::Minimize, Transform::Subtract) => worst - f,
+                };
+                if weight.is_finite() {
+                    weight.max(0.0)
+                } else {
+                    0.0
+                }
+            })
+            .collect();
+    }
+
+    fn select(&self, pop: &Population, _metric: &dyn Metric, rng: &mut dyn RngCore) -> Individual {
+        pop.individuals()[spin(&self.weights, rng)].clone()
+    }
+}
+
+///Linear rank selection.  The best individual is chosen with pressure times and the worst
+/// with 2 - pressure times the average probability, pressure being between 1 and 2
+#[derive(Debug, Clone)]
+pub struct LinearRank {
+    pressure: f32,
+    ranking: Vec<usize>,
+    ///Weight of each rank, best first
+    weights: Vec<f32>,
+}
+
+impl LinearRank {
+    pub fn new(pressure: f32) -> LinearRank {
+        assert!(
+            (1.0..=2.0).contains(&pressure),
+            "Selection pressure must be between 1 and 2"
+        );
+        LinearRank {
+            pressure,
+            ranking: vec![],
+            weights: vec![],
+        }
+    }
+}
+
+impl Selection for LinearRank {
+    fn prepare(&mut self, pop: &Population, metric: &dyn Metric, _rng: &mut dyn RngCore) {
+        self.ranking = ranking(pop, metric);
+        //the weights only depend on the population size
+        let n = self.ranking.len();
+        if self.weights.len() != n {
+            self.weights = (0..n)
+                .map(|rank| match n {
+                    1 => 1.0,
+                    _ => self.pressure - 2.0 * (self.pressure - 1.0) * rank as f32 / (n - 1) as f32,
+                })
+                .collect();
+        }
+    }
+
+    fn select(&self, pop: &Population, _metric: &dyn Metric, rng: &mut dyn RngCore) -> Individual {
+        pop.individuals()[self.ranking[spin(&self.weights, rng)]].clone()
+    }
+}
+
+///Truncation selection, parents are drawn uniformly from the best fraction of the population
+#[derive(Debug, Clone)]
+pub struct Truncation {
+    fraction: f32,
+    ranking: Vec<usize>,
+}
+
+impl Truncation {
+    pub fn new(fraction: f32) -> Truncation {
+        assert!(
+            fraction > 0.0 && fraction <= 1.0,
+            "Truncation fraction must be in (0, 1]"
+        );
+        Truncation {
+            fraction,
+            ranking: vec![],
+        }
+    }
+}
+
+impl Selection for Truncation {
+    fn prepare(&mut self, pop: &Population, metric: &dyn Metric, _rng: &mut dyn RngCore) {
+        self.ranking = ranking(pop, metric);
+        let keep = (self.ranking.len() as f32 * self.fraction).ceil() as usize;
+        self.ranking.truncate(keep.max(1));
+    }
+
+    fn select(&self, pop: &Population, _metric: &dyn Metric, rng: &mut dyn RngCore) -> Individual {
+        pop.individuals()[*self.ranking.choose(rng).expect("empty")].clone()
+    }
+}
+
+///Double tournament for bloat control.  The winners of size_rounds fitness tournaments meet
+/// in a second round on size, where the smaller of two wins with probability parsimony
+/// and the fitter wins ties
+#[derive(Debug, Clone, Copy)]
+pub struct DoubleTournament {
+    fitness: Tournament,
+    size_rounds: usize,
+    parsimony: f32,
+}
+
+impl DoubleTournament {
+    pub fn new(fitness_size: usize, size_rounds: usize, parsimony: f32) -> DoubleTournament {
+        assert!(
+            size_rounds > 0,
+            "Second round needs at least one competitor"
+        );
+        assert!(
+            (0.5..=1.0).contains(&parsimony),
+            "Parsimony must be between 0.5 and 1"
+        );
+        DoubleTournament {
+            fitness: Tournament::new(fitness_size),
+            size_rounds,
+            parsimony,
+        }
+    }
+}
+
+impl Selection for DoubleTournament {
+    fn select(&self, pop: &Population, metric: &dyn Metric, rng: &mut dyn RngCore) -> Individual {
+        let individuals = pop.individuals();
+        let mut champion = self.fitness.winner(pop, metric, rng);
+        for _ in 1..self.size_rounds {
+            let challenger = self.fitness.winner(pop, metric, rng);
+            let (a, b) = (&individuals[champion], &individuals[challenger]);
+            champion = match a.size().cmp(&b.size()) {
+                Ordering::Equal => {
+                    if metric.is_better(fitness(b), fitness(a)) {
+                        challenger
+                    } else {
+                        champion
+                    }
+                }
+                order => {
+                    let smaller_wins = rng.gen::<f32>() < self.parsimony;
+                    if (order == Ordering::Less) == smaller_wins {
+                        champion
+                    } else {
+                        challenger
+                    }
+                }
+            };
+        }
+        individuals[champion].clone()
+    }
+}
+
+///Lexicase selection, filtering the candidates by their absolute error on one training case
+/// at a time in random order and keeping only those with the lowest error
+#[derive(Debug, Clone)]
+pub struct Lexicase {
+    ///Whether a candidate within the median absolute deviation of the best error passes a case
+    epsilon: bool,
+    ///Fraction of the training cases drawn every generation, None to use them all
+    sample: Option<f32>,
+    cases: Vec<usize>,
+    epsilons: Vec<f32>,
+}
+
+impl Lexicase {
+    pub fn new() -> Lexicase {
+        Lexicase {
+            epsilon: false,
+            sample: None,
+            cases: vec![],
+            epsilons: vec![],
+        }
+    }
+
+    ///Epsilon-lexicase, a candidate survives a case if its error is within epsilon of the
+    /// lowest, epsilon being the median absolute deviation of the population's errors on it
+    pub fn epsilon() -> Lexicase {
+        Lexicase {
+            epsilon: true,
+            ..Lexicase::new()
+        }
+    }
+
+    ///Epsilon-lexicase on a random fraction of the training cases, drawn anew every generation
+    pub fn downsampled(rate: f32) -> Lexicase {
+        assert!(rate > 0.0 && rate <= 1.0, "Sampling rate must be in (0, 1]");
+        Lexicase {
+            sample: Some(rate),
+            ..Lexicase::epsilon()
+        }
+    }
+}
+
+impl Default for Lexicase {
+    fn default() -> Self {
+        Lexicase::new()
+    }
+}
+
+///Median absolute deviation of the population's errors on each training case
+fn case_epsilons(pop: &Population) -> Vec<f32> {
+    let individuals = pop.individuals();
+    let cases = individuals[0].case_errors().len();
+    (0..cases)
+        .map(|c| {
+            let errors: Vec<f32> = individuals
+                .iter()
+                .map(|i| i.case_errors()[c])
+                .filter(|e| e.is_finite())
+                .collect();
+            let middle = median(&errors);
+            let deviations: Vec<f32> = errors.iter().map(|e| (e - middle).abs()).collect();
+            median(&deviations)
+        })
+        .collect()
+}
+
+impl Selection for Lexicase {
+    fn prepare(&mut self, pop: &Population, _metric: &dyn Metric, rng: &mut dyn RngCore) {
+        let n = pop.individuals()[0].case_errors().len();
+        self.cases = match self.sample {
+            Some(rate) => {
+                let sample = ((n as f32 * rate).round() as usize).clamp(1, n);
+                rand::seq::index::sample(rng, n, sample).into_vec()
+            }
+            None => (0..n).collect(),
+        };
+        if self.epsilon {
+            self.epsilons = case_epsilons(pop);
+        }
+    }
+
+    fn select(&self, pop: &Population, _metric: &dyn Metric, rng: &mut dyn RngCore) -> Individual {
+        let individuals = pop.individuals();
+        let mut candidates: Vec<&Individual> = individuals
+            .iter()
+            .filter(|i| !fitness(i).is_nan())
+            .collect();
+        if candidates.is_empty() {
+            return individuals.choose(rng).expect("empty").clone();
+        }
+        let mut order = self.cases.clone();
+        order.shuffle(rng);
+        for c in order {
+            if candidates.len() == 1 {
+                break;
+            }
+            //NaN errors never pass a case
+            let best = candidates
+                .iter()
+                .map(|i| i.case_errors()[c])
+                .filter(|e| !e.is_nan())
+                .fold(f32::INFINITY, f32::min);
+            let epsilon = if self.epsilon { self.epsilons[c] } else { 0.0 };
+            let remaining: Vec<&Individual> = candidates
+                .iter()
+                .copied()
+                .filter(|i| i.case_errors()[c] <= best + epsilon)
+                .collect();
+            if !remaining.is_empty() {
+                candidates = remaining;
+            }
+        }
+        (*candidates.choose(rng).expect("empty")).clone()
+    }
+}
@@ -0,0 +1,375 @@
+use crate::base::data::Data;
+use crate::base::function_set::FunctionSet;
+use crate::base::individual::{Individual, Node};
+
+///Relative difference in outputs up to which a rewrite counts as equivalent
+const TOLERANCE: f32 = 1e-4;
+
+///Expression tree built from the prefix chromosome so rewrites can work on whole subtrees
+#[derive(Debug, Clone, PartialEq)]
+struct Tree {
+    node: Node,
+    children: Vec<Tree>,
+}
+
+impl Tree {
+    fn leaf(node: Node) -> Tree {
+        Tree {
+            node,
+            children: vec![],
+        }
+    }
+
+    fn constant(x: f32) -> Tree {
+        Tree::leaf(Node::Constant(x))
+    }
+
+    fn binary(node: Node, left: Tree, right: Tree) -> Tree {
+        Tree {
+            node,
+            children: vec![left, right],
+        }
+    }
+
+    ///Read the subtree starting at index, index is advanced past it
+    fn parse(nodes: &[Node], index: &mut usize) -> Tree {
+        let node = nodes[*index].clone();
+        *index += 1;
+        let children = (0..node.arity())
+            .map(|_| Tree::parse(nodes, index))
+            .collect();
+        Tree { node, children }
+    }
+
+    ///Write the tree back in prefix order
+    fn flatten(&self, out: &mut Vec<Node>) {
+        out.push(self.node.clone());
+        for child in &self.children {
+            child.flatten(out);
+        }
+    }
+
+    fn nodes(&self) -> Vec<Node> {
+        let mut out = vec![];
+        self.flatten(&mut out);
+        out
+    }
+
+    fn size(&self) -> usize {
+        1 + self.children.iter().map(Tree::size).sum::<usize>()
+    }
+
+    fn value(&self) -> Option<f32> {
+        match self.node {
+            Node::Constant(x) => Some(x),
+            _ => None,
+        }
+    }
+
+    fn is(&self, x: f32) -> bool {
+        self.value() == Some(x)
+    }
+}
+
+///Algebraically simplify a chromosome: constant folding, removal of identities (x+0, x*1, x/1)
+/// and annihilators (x*0, 0/x), rewrites such as x-x, x/x, min(x,x) and collection of like
+/// terms in sums.  A rewrite is only kept if the rewritten subtree is smaller and gives the
+/// same outputs on the training data, within a relative tolerance
+pub fn simplify(chromosome: &[Node], datas: &Data, functions: &FunctionSet) -> Vec<Node> {
+    let simplifier = Simplifier { datas, functions };
+    let tree = Tree::parse(chromosome, &mut 0);
+    simplifier.simplify(tree).nodes()
+}
+
+struct Simplifier<'a> {
+    datas: &'a Data,
+    functions: &'a FunctionSet,
+}
+
+impl Simplifier<'_> {
+    ///Simplify the children first, then rewrite the root until no rule applies
+    fn simplify(&self, tree: Tree) -> Tree {
+        let mut tree = Tree {
+            node: tree.node,
+            children: tree
+                .children
+                .into_iter()
+                .map(|c| self.simplify(c))
+                .collect(),
+        };
+        while let Some(rewritten) = self.rewrite(&tree) {
+            tree = rewritten;
+        }
+        tree
+    }
+
+    ///First candidate rewrite that is smaller and numerically equivalent
+    fn rewrite(&self, tree: &Tree) -> Option<Tree> {
+        if tree.children.is_empty() {
+            return None;
+        }
+        let outputs = self.outputs(tree);
+        candidates(tree, self.functions)
+            .into_iter()
+            .filter(|c| c.size() < tree.size())
+            .find(|c| equivalent(&outputs, &self.outputs(c)))
+            .map(|c| {
+                //children of a rewrite may be new combinations, so simplify them again
+                let children = c.children.into_iter().map(|t| self.simplify(t)).collect();
+                Tree {
+                    node: c.node,
+                    children,
+                }
+            })
+    }
+
+    ///Outputs of a subtree on the training data
+    fn outputs(&self, tree: &Tree) -> Vec<f32> {
+        let mut ind = Individual::new();
+        ind.insert(tree.nodes());
+        ind.outputs_calculate(self.datas, self.functions);
+        ind.training_outputs()
+    }
+}
+
+///True if the outputs agree within the tolerance, NaN only agreeing with NaN
+fn equivalent(a: &[f32], b: &[f32]) -> bool {
+    a.iter().zip(b).all(|(&x, &y)| {
+        (x.is_nan() && y.is_nan()) || x == y || (x - y).abs() <= TOLERANCE * x.abs().max(1.0)
+    })
+}
+
+///Possible rewrites of the root of a tree whose children are already simplified
+fn candidates(tree: &Tree, functions: &FunctionSet) -> Vec<Tree> {
+    let mut out = vec![];
+    let c = &tree.children;
+
+    //constant folding
+    if c.iter().all(|t| t.value().is_some()) {
+        let args = c.iter().map(|t| vec![t.value().unwrap()]).collect();
+        let value = functions.apply(&tree.node, args)[0];
+        if value.is_finite() {
+            out.push(Tree::constant(value));
+        }
+    }
+
+    match tree.node {
+        Node::Addition | Node::Subtraction => {
+            if c[1].is(0.0) {
+                out.push(c[0].clone());
+            }
+            if tree.node == Node::Addition && c[0].is(0.0) {
+                out.push(c[1].clone());
+            }
+            if tree.node == Node::Subtraction && c[0] == c[1] {
+                out.push(Tree::constant(0.0));
+            }
+            out.push(collect_terms(tree));
+        }
+        Node::Multiplication => {
+            for (a, b) in [(&c[0], &c[1]), (&c[1], &c[0])] {
+                if a.is(1.0) {
+                    out.push(b.clone());
+                }
+                if a.is(0.0) {
+                    out.push(Tree::constant(0.0));
+                }
+                //c1 * (c2 * x) becomes (c1 * c2) * x
+                if let (Some(k), Node::Multiplication) = (a.value(), &b.node) {
+                    for (d, x) in [
+                        (&b.children[0], &b.children[1]),
+                        (&b.children[1], &b.children[0]),
+                    ] {
+                        if let Some(j) = d.value() {
+                            out.push(Tree::binary(
+                                Node::Multiplication,
+                                Tree::constant(k * j),
+                                x.clone(),
+                            ));
+                        }
+                    }
+                }
+            }
+        }
+        Node::Division => {
+            if c[1].is(1.0) {
+                out.push(c[0].clone());
+            }
+            if c[0].is(0.0) {
+                out.push(Tree::constant(0.0));
+            }
+            if c[0] == c[1] {
+                out.push(Tree::constant(1.0));
+            }
+        }
+        Node::Min | Node::Max if c[0] == c[1] => out.push(c[0].clone()),
+        Node::Power => {
+            if c[1].is(0.0) {
+                out.push(Tree::constant(1.0));
+            }
+            if c[1].is(1.0) {
+                out.push(Tree {
+                    node: Node::Abs,
+                    children: vec![c[0].clone()],
+                });
+            }
+        }
+        Node::IfGreaterThanZero => {
+            if c[1] == c[2] {
+                out.push(c[1].clone());
+            }
+            match c[0].value() {
+                Some(x) if x > 0.0 => out.push(c[1].clone()),
+                Some(_) => out.push(c[2].clone()),
+                None => {}
+            }
+        }
+        _ => {}
+    }
+    out
+}
+
+///Split a sum into (coefficient, term) pairs, a term of None being the constant part
+fn sum_terms(tree: &Tree, sign: f32, terms: &mut Vec<(f32, Option<Tree>)>) {
+    match tree.node {
+        Node::Addition => {
+            sum_terms(&tree.children[0], sign, terms);
+            sum_terms(&tree.children[1], sign, terms);
+        }
+        Node::Subtraction => {
+            sum_terms(&tree.children[0], sign, terms);
+            sum_terms(&tree.children[1], -sign, terms);
+        }
+        Node::Constant(x) => terms.push((sign * x, None)),
+        Node::Multiplication if tree.children[0].value().is_some() => terms.push((
+            sign * tree.children[0].value().unwrap(),
+            Some(tree.children[1].clone()),
+        )),
+        Node::Multiplication if tree.children[1].value().is_some() => terms.push((
+            sign * tree.children[1].value().unwrap(),
+            Some(tree.children[0].clone()),
+        )),
+        _ => terms.push((sign, Some(tree.clone()))),
+    }
+}
+
+///Rewrite a sum with its like terms collected, e.g. x + 2*x - 1 + 3 becomes 3*x + 2
+fn collect_terms(tree: &Tree) -> Tree {
+    let mut terms = vec![];
+    sum_terms(tree, 1.0, &mut terms);
+    let mut collected: Vec<(f32, Option<Tree>)> = vec![];
+    for (coefficient, term) in terms {
+        match collected.iter_mut().find(|(_, t)| *t == term) {
+            Some((total, _)) => *total += coefficient,
+            None => collected.push((coefficient, term)),
+        }
+    }
+    collected.retain(|(coefficient, _)| *coefficient != 0.0);
+    //start with a positive term so the rest can be added or subtracted
+    if let Some(i) = collected.iter().position(|(k, _)| *k > 0.0) {
+        let first = collected.remove(i);
+        collected.insert(0, first);
+    }
+
+    let mut sum: Option<Tree> = None;
+    for (coefficient, term) in collected {
+        let magnitude = match &sum {
+            Some(_) => coefficient.abs(),
+            None => coefficient,
+        };
+        let part = match term {
+            None => Tree::constant(magnitude),
+            Some(t) if magnitude == 1.0 => t,
+            Some(t) => Tree::binary(Node::Multiplication, Tree::constant(magnitude), t),
+        };
+        sum = Some(match sum {
+            None => part,
+            Some(s) if coefficient < 0.0 => Tree::binary(Node::Subtraction, s, part),
+            Some(s) => Tree::binary(Node::Addition, s, part),
+        });
+    }
+    sum.unwrap_or_else(|| Tree::constant(0.0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::base::fitness::{LinearScaling, Rmse};
+    use Node::*;
+
+    ///y = 3x + 2 with x = 0 first so it stays in the chronological training rows
+    fn data() -> Data {
+        let x: Vec<f32> = std::iter::once(0.0)
+            .chain((1..20).map(|i| -2.0 + 0.2 * i as f32))
+            .collect();
+        let y: Vec<f32> = x.iter().map(|x| 3.0 * x + 2.0).collect();
+        Data::from_series(&["x0", "y"], &[x, y], 0.2)
+            .unwrap()
+            .set_chronological(true)
+    }
+
+    fn simplified(chromosome: Vec<Node>) -> Vec<Node> {
+        simplify(&chromosome, &data(), &FunctionSet::new())
+    }
+
+    #[test]
+    fn difference_with_itself_is_zero() {
+        let x_minus_x = vec![Subtraction, Input(0), Input(0)];
+        assert_eq!(simplified(x_minus_x), vec![Constant(0.0)]);
+    }
+
+    #[test]
+    fn like_terms_are_collected() {
+        let sum = vec![
+            Addition,
+            Multiplication,
+            Constant(2.0),
+            Input(0),
+            Multiplication,
+            Constant(3.0),
+            Input(0),
+        ];
+        assert_eq!(
+            simplified(sum),
+            vec![Multiplication, Constant(5.0), Input(0)]
+        );
+    }
+
+    #[test]
+    fn constant_factors_are_folded() {
+        let product = vec![
+            Multiplication,
+            Constant(2.0),
+            Multiplication,
+            Input(0),
+            Constant(4.0),
+        ];
+        assert_eq!(
+            simplified(product),
+            vec![Multiplication, Constant(8.0), Input(0)]
+        );
+    }
+
+    #[test]
+    fn protected_quotient_with_itself_is_kept() {
+        //protected division returns the numerator 0 at x = 0, not 1
+        let x_over_x = vec![Division, Input(0), Input(0)];
+        assert_eq!(simplified(x_over_x.clone()), x_over_x);
+    }
+
+    #[test]
+    fn scaled_model_is_refitted() {
+        let (datas, functions) = (data(), FunctionSet::new());
+        let metric = LinearScaling::new(Rmse);
+        let mut ind = Individual::new();
+        ind.insert(vec![Addition, Input(0), Subtraction, Input(0), Input(0)]);
+        ind.outputs_calculate(&datas, &functions);
+        ind.eval_fitness(&datas, &metric);
+
+        let simple = ind.simplify(&datas, &functions, &metric);
+        assert_eq!(simple.cloned(), vec![Input(0)]);
+        let (intercept, slope) = simple.linear_scaling().unwrap();
+        assert!((intercept - 2.0).abs() < 1e-4 && (slope - 3.0).abs() < 1e-4);
+        assert!(simple.train_fit().unwrap() < 1e-4);
+    }
+}
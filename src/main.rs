@@ -1,11 +1,7 @@
-extern crate rand;
-
-mod base;
-use base::data::Data;
-use base::gp::Gp;
+use corroded_gp::base::data::Data;
+use corroded_gp::base::gp::Gp;
 
 ///Entry point to the system
-
 fn main() {
     //When establishing dataset need a string for the file path and a float giving what percent to use for testing
     let dataset = Data::new("sin-data.txt", 0.5);